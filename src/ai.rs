@@ -1,83 +1,310 @@
-use bevy::{prelude::*, window::PrimaryWindow};
+use std::{collections::{BTreeMap, VecDeque}, f32::consts::{PI, TAU}};
+
+use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
+use rand::Rng;
 
-use crate::{Player, game::{Ball, Paddle}};
+use crate::{Paused, Player, game::{Arena, Ball, GameRng, Paddle, Wall}};
 
 pub struct AiPlugin;
 
 impl Plugin for AiPlugin {
     fn build(&self, app: &mut App) {
         app
-        .add_state::<Opponent>()
-        .insert_resource(AiBrain::new_simple())
-        .add_systems(Last, update_ai);
+        .init_resource::<AiBrains>()
+        .init_resource::<SeatSelection>()
+        .init_resource::<AiSettings>()
+        .register_type::<AiSettings>()
+        .add_systems(FixedUpdate, update_ai.in_set(AiUpdate).run_if(in_state(Paused::Running)));
     }
 }
 
-#[derive(Resource)]
+/// ais decide what to do here, before anything moves in the tick
+#[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AiUpdate;
+
 pub struct AiBrain(Box<dyn PongAi>);
 
+/// the ai driving each seat, seats without one are human.
+/// Kept in seat order so the ais always take their turns at [`GameRng::ai_rng`] the same way
+#[derive(Resource, Default)]
+pub struct AiBrains(pub BTreeMap<Player, AiBrain>);
+
+impl AiBrains {
+    pub fn get(&self, player: Player) -> Option<&AiBrain> {
+        self.0.get(&player)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeatKind {
+    Human,
+    Simple,
+    Goaly,
+    Smart,
+    Predictive,
+}
+
+impl SeatKind {
+    /// every ai that can be put in a seat
+    pub const AI: [SeatKind; 4] = [SeatKind::Simple, SeatKind::Goaly, SeatKind::Smart, SeatKind::Predictive];
+
+    pub fn next(self) -> SeatKind {
+        match self {
+            SeatKind::Human => SeatKind::Simple,
+            SeatKind::Simple => SeatKind::Goaly,
+            SeatKind::Goaly => SeatKind::Smart,
+            SeatKind::Smart => SeatKind::Predictive,
+            SeatKind::Predictive => SeatKind::Human,
+        }
+    }
+
+    pub fn brain(self) -> Option<AiBrain> {
+        match self {
+            SeatKind::Human => None,
+            SeatKind::Simple => Some(AiBrain::new_simple()),
+            SeatKind::Goaly => Some(AiBrain::new_goaly()),
+            SeatKind::Smart => Some(AiBrain::new_smart()),
+            SeatKind::Predictive => Some(AiBrain::new_predictive()),
+        }
+    }
+}
+
+/// who is picked to play in each seat on the opponent select menu
+#[derive(Debug, Resource)]
+pub struct SeatSelection {
+    pub player1: SeatKind,
+    pub player2: SeatKind,
+    pub player3: SeatKind,
+    pub player4: SeatKind,
+}
+
+impl Default for SeatSelection {
+    fn default() -> Self {
+        SeatSelection { player1: SeatKind::Human, player2: SeatKind::Simple, player3: SeatKind::Simple, player4: SeatKind::Simple }
+    }
+}
+
+impl SeatSelection {
+    pub fn get(&self, player: Player) -> SeatKind {
+        match player {
+            Player::PlayerOne => self.player1,
+            Player::PlayerTwo => self.player2,
+            Player::PlayerThree => self.player3,
+            Player::PlayerFour => self.player4,
+        }
+    }
+
+    /// a fresh brain for every ai seat
+    pub fn brains(&self, tuning: AiTuning) -> AiBrains {
+        let mut brains = BTreeMap::new();
+        for player in Player::ALL {
+            if let Some(brain) = self.get(player).brain() {
+                brains.insert(player, brain.with_difficulty(tuning));
+            }
+        }
+        AiBrains(brains)
+    }
+}
+
 impl AiBrain {
     pub fn new_simple() -> AiBrain {
-        AiBrain(Box::new(SimplePongAi{delta: 0.}))
+        AiBrain(Box::new(SimplePongAi{delta: 0., aim: 0.}))
     }
 
     pub fn new_goaly() -> AiBrain {
-        AiBrain(Box::new(GoalyPongAi{delta: 0.}))
+        AiBrain(Box::new(GoalyPongAi{delta: 0., aim: 0.}))
     }
 
     pub fn new_smart() -> AiBrain {
-        AiBrain(Box::new(SmartPongAi{delta: 0.}))
+        AiBrain(Box::new(SmartPongAi{delta: 0., aim: 0.}))
+    }
+
+    pub fn new_predictive() -> AiBrain {
+        AiBrain(Box::new(PredictivePongAi{delta: 0., aim: 0.}))
+    }
+
+    pub fn new_orbit() -> AiBrain {
+        AiBrain(Box::new(OrbitPongAi{turn: 0., aim: 0.}))
+    }
+
+    pub fn with_difficulty(self, difficulty: AiTuning) -> AiBrain {
+        AiBrain(Box::new(TunedPongAi {
+            inner: self.0,
+            tuning: difficulty,
+            pending: VecDeque::new(),
+            control: AiControl::default(),
+            hesitate_for: 0,
+            frames_to_reaim: 0,
+        }))
     }
 }
 
 fn update_ai(
     world: &mut World,
 ) {
-    world.resource_scope(|world, mut ai: Mut<AiBrain>| {
-        ai.update(world);
+    world.resource_scope(|world, mut brains: Mut<AiBrains>| {
+        for (player, brain) in brains.0.iter_mut() {
+            brain.update(world, *player);
+        }
     });
 }
 
 impl PongAi for AiBrain {
-    fn get_delta(&self) -> f32 {
-        self.0.get_delta()
+    fn control(&self) -> AiControl {
+        self.0.control()
+    }
+    fn update(&mut self, world: &mut World, player: Player) {
+        self.0.update(world, player)
     }
-    fn update(&mut self, world: &mut World) {
-        self.0.update(world)
+    fn set_aim_error(&mut self, error: f32) {
+        self.0.set_aim_error(error)
     }
 }
 
-#[derive(Debug, Default, States, Hash, PartialEq, Eq, Clone, Copy)]
-pub enum Opponent {
-    #[default]
-    Human,
-    Ai,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum AiDifficulty {
+    Easy,
+    Medium,
+    Hard,
+    Custom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct AiTuning {
+    /// frames between the ai seeing something and reacting to it
+    pub reaction_frames: usize,
+    /// standard deviation of the aim error, in pixels
+    pub aim_error: f32,
+    /// fraction of `Paddle::speed` the ai is allowed to use
+    pub max_speed: f32,
+    /// chance each time it re-aims that it freezes for a moment instead
+    pub hesitation: f64,
+}
+
+impl AiTuning {
+    pub const EASY: AiTuning = AiTuning { reaction_frames: 15, aim_error: 50., max_speed: 0.6, hesitation: 0.15 };
+    pub const MEDIUM: AiTuning = AiTuning { reaction_frames: 6, aim_error: 20., max_speed: 0.8, hesitation: 0.05 };
+    pub const HARD: AiTuning = AiTuning { reaction_frames: 0, aim_error: 0., max_speed: 1., hesitation: 0. };
+
+    /// back in the range the menu offers, with the fractions on the menu's steps of 0.05
+    fn clamped(self) -> AiTuning {
+        AiTuning {
+            reaction_frames: self.reaction_frames.min(60),
+            aim_error: self.aim_error.clamp(0., 200.),
+            max_speed: ((self.max_speed * 20.).round() / 20.).clamp(0.1, 1.),
+            hesitation: ((self.hesitation * 20.).round() / 20.).clamp(0., 1.),
+        }
+    }
+}
+
+#[derive(Debug, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct AiSettings {
+    pub difficulty: AiDifficulty,
+    /// used when `difficulty` is [`AiDifficulty::Custom`]
+    pub custom: AiTuning,
+}
+
+impl Default for AiSettings {
+    fn default() -> Self {
+        AiSettings { difficulty: AiDifficulty::Medium, custom: AiTuning::MEDIUM }
+    }
+}
+
+impl AiSettings {
+    pub fn tuning(&self) -> AiTuning {
+        match self.difficulty {
+            AiDifficulty::Easy => AiTuning::EASY,
+            AiDifficulty::Medium => AiTuning::MEDIUM,
+            AiDifficulty::Hard => AiTuning::HARD,
+            AiDifficulty::Custom => self.custom,
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.difficulty = match self.difficulty {
+            AiDifficulty::Easy => AiDifficulty::Medium,
+            AiDifficulty::Medium => AiDifficulty::Hard,
+            AiDifficulty::Hard => AiDifficulty::Custom,
+            AiDifficulty::Custom => AiDifficulty::Easy,
+        };
+    }
+
+    /// edits the custom tuning and switches over to it
+    pub fn adjust(&mut self, change: impl FnOnce(&mut AiTuning)) {
+        change(&mut self.custom);
+        self.custom = self.custom.clamped();
+        self.difficulty = AiDifficulty::Custom;
+    }
+}
+
+/// what an ai wants to do with its paddle this tick, each mode reads the kind it understands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AiControl {
+    /// along the paddle's lane from -1 to 1 of its top speed, up or right is positive
+    Slide(f32),
+    /// round the orbit from -1 to 1 of the moon's top speed, anticlockwise is positive
+    Turn(f32),
+}
+
+impl Default for AiControl {
+    fn default() -> Self {
+        AiControl::Slide(0.)
+    }
+}
+
+impl AiControl {
+    pub fn slide(self) -> f32 {
+        match self {
+            AiControl::Slide(delta) => delta,
+            AiControl::Turn(_) => 0.,
+        }
+    }
+
+    pub fn turn(self) -> f32 {
+        match self {
+            AiControl::Turn(turn) => turn,
+            AiControl::Slide(_) => 0.,
+        }
+    }
+
+    fn map(self, f: impl Fn(f32) -> f32) -> AiControl {
+        match self {
+            AiControl::Slide(delta) => AiControl::Slide(f(delta)),
+            AiControl::Turn(turn) => AiControl::Turn(f(turn)),
+        }
+    }
 }
 
 pub trait PongAi: 'static + Send + Sync {
-    fn get_delta(&self) -> f32;
-    fn update(&mut self, world: &mut World);
+    fn control(&self) -> AiControl;
+    /// `player` is the seat this ai is driving
+    fn update(&mut self, world: &mut World, player: Player);
+    /// offset added to wherever the ai is aiming
+    fn set_aim_error(&mut self, _error: f32) {}
 }
 
 struct SimplePongAi {
     delta: f32,
+    aim: f32,
 }
 
 impl PongAi for SimplePongAi {
-    fn get_delta(&self) -> f32 {
-        self.delta
-    }
-    fn update(&mut self, world: &mut World) {
-        let mut query = world.query_filtered::<(&Player, &Transform), With<Paddle>>();
-        let mut balls = world.query_filtered::<&Transform, With<Ball>>();
-        for (player, pos) in query.iter(world) {
-            if Player::PlayerTwo.ne(player) {continue;}
-            let mut ball_pos = Vec3::NEG_X * 1000.;
-            for ball in balls.iter(&world) {
-                if ball.translation.x > ball_pos.x {ball_pos = ball.translation };
-            }
-            let error = ball_pos.y - pos.translation.y;
+    fn control(&self) -> AiControl {
+        AiControl::Slide(self.delta)
+    }
+    fn set_aim_error(&mut self, error: f32) {
+        self.aim = error;
+    }
+    fn update(&mut self, world: &mut World, player: Player) {
+        let mut query = world.query::<(&Player, &Transform, &Paddle)>();
+        let mut balls = world.query_filtered::<(&Transform, &Velocity), With<Ball>>();
+        for (owner, pos, paddle) in query.iter(world) {
+            if player.ne(owner) {continue;}
+            let pos = view(paddle, pos.translation);
+            let ball_y = most_threatening(paddle, pos, balls.iter(&world)).map_or(0., |(ball, _)| ball.y);
+            let error = ball_y + self.aim - pos.y;
             if error > 10. {
                 self.delta = 1.;
             } else if error < -10. {
@@ -91,22 +318,27 @@ impl PongAi for SimplePongAi {
 
 struct GoalyPongAi {
     delta: f32,
+    aim: f32,
 }
 
 impl PongAi for GoalyPongAi {
-    fn get_delta(&self) -> f32 {
-        self.delta
-    }
-    fn update(&mut self, world: &mut World) {
-        let mut query = world.query_filtered::<(&Player, &Transform), With<Paddle>>();
-        let mut balls = world.query_filtered::<&Transform, With<Ball>>();
-        for (player, pos) in query.iter(world) {
-            if Player::PlayerTwo.ne(player) {continue;}
-            let mut ball_pos = Vec3::ZERO;
-            for ball in balls.iter(&world) {
-                if ball.translation.x > ball_pos.x {ball_pos = ball.translation };
-            }
-            let error = ball_pos.y - pos.translation.y;
+    fn control(&self) -> AiControl {
+        AiControl::Slide(self.delta)
+    }
+    fn set_aim_error(&mut self, error: f32) {
+        self.aim = error;
+    }
+    fn update(&mut self, world: &mut World, player: Player) {
+        let mut query = world.query::<(&Player, &Transform, &Paddle)>();
+        let mut balls = world.query_filtered::<(&Transform, &Velocity), With<Ball>>();
+        for (owner, pos, paddle) in query.iter(world) {
+            if player.ne(owner) {continue;}
+            let pos = view(paddle, pos.translation);
+            // stays home until a ball crosses into its half
+            let ball_y = most_threatening(paddle, pos, balls.iter(&world))
+                .filter(|(ball, _)| ball.x * pos.x.signum() > 0.)
+                .map_or(0., |(ball, _)| ball.y);
+            let error = ball_y + self.aim - pos.y;
             if error > 10. {
                 self.delta = 1.;
             } else if error < -10. {
@@ -120,33 +352,31 @@ impl PongAi for GoalyPongAi {
 
 struct SmartPongAi {
     delta: f32,
+    aim: f32,
 }
 
 impl PongAi for SmartPongAi {
-    fn get_delta(&self) -> f32 {
-        self.delta
+    fn control(&self) -> AiControl {
+        AiControl::Slide(self.delta)
+    }
+    fn set_aim_error(&mut self, error: f32) {
+        self.aim = error;
     }
-    fn update(&mut self, world: &mut World) {
-        let mut query = world.query_filtered::<(&Player, &Transform), With<Paddle>>();
+    fn update(&mut self, world: &mut World, player: Player) {
+        let mut query = world.query::<(&Player, &Transform, &Paddle)>();
         let mut balls = world.query_filtered::<(&Transform, &Velocity), With<Ball>>();
-        let window = world.query_filtered::<&Window, With<PrimaryWindow>>().single(&world);
-        for (player, pos) in query.iter(world) {
-            if Player::PlayerTwo.ne(player) {continue;}
-            let mut ball_pos = Vec3::NEG_X * 1000.;
-            let mut ball_speed = Vec2::ZERO;
-            for (ball, speed) in balls.iter(&world) {
-                if speed.linvel.x > 0. {
-                    if ball.translation.x > ball_pos.x {
-                        ball_pos = ball.translation;
-                        ball_speed = speed.linvel;
-                    };
-                }
-            }
-            let x_dif = pos.translation.x - ball_pos.x;
+        let arena = *world.resource::<Arena>();
+        for (owner, pos, paddle) in query.iter(world) {
+            if player.ne(owner) {continue;}
+            let pos = view(paddle, pos.translation);
+            let arena_size = view(paddle, arena.size.extend(0.));
+            let Some((ball_pos, ball_speed)) = most_threatening(paddle, pos, balls.iter(&world)) else {continue;};
+            if ball_speed.x * pos.x.signum() <= 0. {continue;}
+            let x_dif = pos.x - ball_pos.x;
             let y_steps = x_dif / ball_speed.x;
             let y_dif = y_steps * ball_speed.y;
-            let y_dif = y_dif % window.height();
-            let error = ball_pos.y + y_dif - pos.translation.y;
+            let y_dif = y_dif % arena_size.y;
+            let error = ball_pos.y + y_dif + self.aim - pos.y;
             if error > 10. {
                 self.delta = 1.;
             } else if error < -10. {
@@ -156,4 +386,459 @@ impl PongAi for SmartPongAi {
             };
         }
     }
-}
\ No newline at end of file
+}
+
+/// every ai is written for a paddle sliding up and down,
+/// paddles that slide left and right see the world with x and y swapped
+fn view(paddle: &Paddle, v: Vec3) -> Vec3 {
+    if paddle.horizontal {Vec3::new(v.y, v.x, v.z)} else {v}
+}
+
+/// the ball that will reach the paddle at `pos` soonest, or the nearest one while none are coming,
+/// returned as seen through [`view`]
+fn most_threatening<'a>(paddle: &Paddle, pos: Vec3, balls: impl Iterator<Item = (&'a Transform, &'a Velocity)>) -> Option<(Vec3, Vec2)> {
+    let mut best = None;
+    let mut soonest = f32::INFINITY;
+    for (ball, velocity) in balls {
+        let ball = view(paddle, ball.translation);
+        let velocity = view(paddle, velocity.linvel.extend(0.)).truncate();
+        let distance = pos.x - ball.x;
+        let time = if velocity.x != 0. && distance.signum() == velocity.x.signum() {
+            distance / velocity.x
+        } else {
+            // anything coming is more urgent than anything that isn't
+            1e6 + distance.abs()
+        };
+        if time < soonest {
+            soonest = time;
+            best = Some((ball, velocity));
+        }
+    }
+    best
+}
+
+/// where a ball at `pos` moving at `vel` will be when it reaches `target_x`,
+/// bouncing off walls that keep its centre between `min_y` and `max_y`, either can be infinite for an open side.
+/// `None` if it is not heading towards `target_x`
+pub fn predict_intercept(pos: Vec2, vel: Vec2, target_x: f32, min_y: f32, max_y: f32) -> Option<f32> {
+    if vel.x == 0. || (target_x - pos.x).signum() != vel.x.signum() {return None;}
+    let time = (target_x - pos.x) / vel.x;
+    let unfolded = pos.y + vel.y * time;
+    let span = max_y - min_y;
+    if span <= 0. {return Some((min_y + max_y) / 2.);}
+    // with a side open it can only come off the other wall once
+    if span.is_infinite() {
+        return Some(if unfolded < min_y {
+            2. * min_y - unfolded
+        } else if unfolded > max_y {
+            2. * max_y - unfolded
+        } else {
+            unfolded
+        });
+    }
+    // unfold the bounces into a straight line then fold it back into the arena
+    let along = (unfolded - min_y).rem_euclid(2. * span);
+    if along <= span {
+        Some(min_y + along)
+    } else {
+        Some(max_y - (along - span))
+    }
+}
+
+struct PredictivePongAi {
+    delta: f32,
+    aim: f32,
+}
+
+impl PongAi for PredictivePongAi {
+    fn control(&self) -> AiControl {
+        AiControl::Slide(self.delta)
+    }
+    fn set_aim_error(&mut self, error: f32) {
+        self.aim = error;
+    }
+    fn update(&mut self, world: &mut World, player: Player) {
+        let Some((pos, target)) = predict_for(world, player) else {return;};
+        // drift back to the middle while nothing is coming
+        let error = target.map_or(0., |target| target.y) + self.aim - pos.y;
+        if error > 10. {
+            self.delta = 1.;
+        } else if error < -10. {
+            self.delta = -1.;
+        } else if error.abs() < 1. {
+            self.delta = 0.;
+        };
+    }
+}
+
+/// `player`'s paddle and where the first ball to reach it will cross the front of it, both seen through [`view`].
+/// Bounces come off the nearest [`Wall`] either side that reaches across the paddle's lane,
+/// blocks out in the middle of the field aren't modelled
+fn predict_for(world: &mut World, player: Player) -> Option<(Vec3, Option<Vec2>)> {
+    let mut query = world.query::<(&Player, &Transform, &Paddle)>();
+    let mut balls = world.query_filtered::<(&Transform, &Velocity, &Collider), With<Ball>>();
+    let mut walls = world.query_filtered::<(&Transform, &Collider), With<Wall>>();
+    let (_, pos, paddle) = query.iter(world).find(|(owner, ..)| **owner == player)?;
+    let pos = view(paddle, pos.translation);
+    let size = view(paddle, paddle.size.extend(0.));
+    let face = pos.x - pos.x.signum() * size.x / 2.;
+    let (mut min_y, mut max_y) = (f32::NEG_INFINITY, f32::INFINITY);
+    for (wall, collider) in walls.iter(world) {
+        let Some(cuboid) = collider.as_cuboid() else {continue;};
+        let centre = view(paddle, wall.translation);
+        let half_size = view(paddle, cuboid.half_extents().extend(0.));
+        if (face - centre.x).abs() > half_size.x {continue;}
+        if centre.y > pos.y {
+            max_y = max_y.min(centre.y - half_size.y);
+        } else {
+            min_y = min_y.max(centre.y + half_size.y);
+        }
+    }
+    let mut target = None;
+    let mut soonest = f32::INFINITY;
+    for (ball, speed, collider) in balls.iter(world) {
+        let radius = collider.as_ball().map_or(0., |ball| ball.radius());
+        let contact_x = face - pos.x.signum() * radius;
+        let ball_pos = view(paddle, ball.translation).truncate();
+        let velocity = view(paddle, speed.linvel.extend(0.)).truncate();
+        let Some(y) = predict_intercept(ball_pos, velocity, contact_x, min_y + radius, max_y - radius) else {continue;};
+        let time = (contact_x - ball_pos.x) / velocity.x;
+        if time < soonest {
+            soonest = time;
+            target = Some(Vec2::new(contact_x, y));
+        }
+    }
+    Some((pos, target))
+}
+
+/// how often a tuned ai rolls a new aim error and maybe hesitates
+const REAIM_FRAMES: usize = 30;
+
+/// wraps another ai to make it worse at the game
+struct TunedPongAi {
+    inner: Box<dyn PongAi>,
+    tuning: AiTuning,
+    pending: VecDeque<AiControl>,
+    control: AiControl,
+    hesitate_for: usize,
+    frames_to_reaim: usize,
+}
+
+impl PongAi for TunedPongAi {
+    fn control(&self) -> AiControl {
+        self.control
+    }
+    fn update(&mut self, world: &mut World, player: Player) {
+        self.inner.update(world, player);
+        let mut rng = world.resource_mut::<GameRng>();
+        let rng = rng.ai_rng();
+        if self.frames_to_reaim == 0 {
+            self.frames_to_reaim = REAIM_FRAMES;
+            self.inner.set_aim_error(gaussian(rng) * self.tuning.aim_error);
+            if self.tuning.hesitation > 0. && rng.gen_bool(self.tuning.hesitation.min(1.)) {
+                self.hesitate_for = self.tuning.reaction_frames.max(10);
+            }
+        }
+        self.frames_to_reaim -= 1;
+
+        let control = self.inner.control();
+        self.pending.push_back(control);
+        let delayed = if self.pending.len() > self.tuning.reaction_frames {
+            self.pending.pop_front().unwrap_or_default()
+        } else {
+            control.map(|_| 0.)
+        };
+        self.control = if self.hesitate_for > 0 {
+            self.hesitate_for -= 1;
+            delayed.map(|_| 0.)
+        } else {
+            delayed.map(|value| value.clamp(-self.tuning.max_speed, self.tuning.max_speed))
+        };
+    }
+}
+
+/// when a ball at `pos` moving at `vel` will first be `radius` from the middle, `None` if it never gets there
+pub fn predict_crossing(pos: Vec2, vel: Vec2, radius: f32) -> Option<f32> {
+    let a = vel.length_squared();
+    if a == 0. {return None;}
+    let half_b = pos.dot(vel);
+    let c = pos.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0. {return None;}
+    // from inside the only way is out, from outside the first touch is on the way in
+    let root = if c > 0. {-discriminant.sqrt()} else {discriminant.sqrt()};
+    let time = (-half_b + root) / a;
+    if time < 0. {None} else {Some(time)}
+}
+
+/// steers a moon to where the earth is going to cross its orbit
+struct OrbitPongAi {
+    turn: f32,
+    /// in pixels along the orbit like the other ais
+    aim: f32,
+}
+
+/// how far off the target angle, in radians, before the moon turns at full speed
+const ORBIT_FULL_TURN: f32 = 0.2;
+
+impl PongAi for OrbitPongAi {
+    fn control(&self) -> AiControl {
+        AiControl::Turn(self.turn)
+    }
+    fn set_aim_error(&mut self, error: f32) {
+        self.aim = error;
+    }
+    fn update(&mut self, world: &mut World, player: Player) {
+        let mut query = world.query::<(&Player, &Transform, &Paddle)>();
+        let mut balls = world.query_filtered::<(&Transform, &Velocity, &Collider), With<Ball>>();
+        for (owner, pos, paddle) in query.iter(world) {
+            if player.ne(owner) {continue;}
+            let moon = pos.translation.truncate();
+            let orbit = moon.length();
+            if orbit == 0. {continue;}
+            let mut target = None;
+            let mut soonest = f32::INFINITY;
+            for (ball, velocity, collider) in balls.iter(&world) {
+                let radius = collider.as_ball().map_or(0., |ball| ball.radius());
+                // the earth's centre is this far out when it touches the inside of the moon
+                let contact = orbit - paddle.size.x / 2. - radius;
+                let ball_pos = ball.translation.truncate();
+                let Some(time) = predict_crossing(ball_pos, velocity.linvel, contact) else {continue;};
+                if time < soonest {
+                    soonest = time;
+                    target = Some(ball_pos + velocity.linvel * time);
+                }
+            }
+            let Some(target) = target else {
+                self.turn = 0.;
+                continue;
+            };
+            let target_angle = target.y.atan2(target.x) + self.aim / orbit;
+            let diff = (target_angle - moon.y.atan2(moon.x) + PI).rem_euclid(TAU) - PI;
+            self.turn = (diff / ORBIT_FULL_TURN).clamp(-1., 1.);
+        }
+    }
+}
+
+/// standard normal sample using the Box-Muller transform
+fn gaussian(rng: &mut impl Rng) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.);
+    let u2: f32 = rng.gen();
+    (-2. * u1.ln()).sqrt() * (TAU * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GameState, game::{headless_app, ArenaLayout, SelectedLayout}};
+
+    const ARENA: Vec2 = Vec2::new(1280., 720.);
+
+    /// a field with its walls well inside the arena, the old guess from the arena's size missed these
+    const NARROW: &str = "(
+        name: \"Narrow\",
+        walls: [
+            (pos: (0.0, 0.3), size: (1.0, 0.015)),
+            (pos: (0.0, -0.3), size: (1.0, 0.015)),
+        ],
+        goals: [
+            (player: PlayerOne, pos: (-0.5, 0.0), size: (0.008, 0.6)),
+            (player: PlayerTwo, pos: (0.5, 0.0), size: (0.008, 0.6)),
+        ],
+        lanes: [
+            (player: PlayerOne, pos: (-0.4, 0.0), size: (0.03, 0.1), travel: 0.25),
+            (player: PlayerTwo, pos: (0.4, 0.0), size: (0.03, 0.1), travel: 0.25),
+        ],
+    )";
+
+    /// sends a ball from `from` at `velocity` in a headless match with the paddles taken out of its way,
+    /// returns the height player two's prediction expects it at and the height rapier actually takes it to
+    fn predicted_and_actual(layout: Option<ArenaLayout>, from: Vec2, velocity: Vec2) -> (f32, f32) {
+        let mut app = headless_app(ARENA);
+        app.insert_resource(SelectedLayout::fixed(layout));
+        app.world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+        let mut balls = app.world.query_filtered::<Entity, With<Ball>>();
+        let mut ball = None;
+        for _ in 0..10 {
+            app.update();
+            ball = balls.iter(&app.world).next();
+            if ball.is_some() {break;}
+        }
+        let ball = ball.expect("a ball is served");
+        let paddles: Vec<Entity> = app.world.query_filtered::<Entity, With<Paddle>>().iter(&app.world).collect();
+        for paddle in paddles {
+            app.world.entity_mut(paddle).remove::<Collider>();
+        }
+        app.world.entity_mut(ball).insert((Transform::from_translation(from.extend(0.)), Velocity::linear(velocity)));
+
+        let (_, target) = predict_for(&mut app.world, Player::PlayerTwo).expect("player two has a paddle");
+        let target = target.expect("the ball is heading for player two");
+        let mut last = from;
+        for _ in 0..3000 {
+            app.update();
+            let pos = app.world.get::<Transform>(ball).expect("the ball is still in play").translation.truncate();
+            if pos.x >= target.x {
+                let t = (target.x - last.x) / (pos.x - last.x);
+                return (target.y, last.y + (pos.y - last.y) * t);
+            }
+            last = pos;
+        }
+        panic!("the ball never reached player two");
+    }
+
+    /// ball positions over the first few seconds of a medium ai against a medium ai
+    fn ai_match(seed: u64) -> Vec<Vec2> {
+        let mut app = headless_app(ARENA);
+        app.insert_resource(crate::game::MatchSeed(Some(seed)));
+        let seats = SeatSelection { player1: SeatKind::Smart, player2: SeatKind::Predictive, ..Default::default() };
+        app.insert_resource(seats.brains(AiTuning::MEDIUM));
+        app.world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+        let mut balls = app.world.query_filtered::<&Transform, With<Ball>>();
+        (0..600).flat_map(|_| {
+            app.update();
+            balls.iter(&app.world).map(|ball| ball.translation.truncate()).collect::<Vec<_>>()
+        }).collect()
+    }
+
+    #[test]
+    fn same_seed_same_ai_match() {
+        assert_eq!(ai_match(7), ai_match(7));
+    }
+
+    #[test]
+    fn straight_line_without_walls() {
+        assert_eq!(predict_intercept(Vec2::ZERO, Vec2::new(1., 0.5), 100., f32::NEG_INFINITY, f32::INFINITY), Some(50.));
+        assert_eq!(predict_intercept(Vec2::ZERO, Vec2::new(-1., 0.5), 100., -10., 10.), None);
+    }
+
+    #[test]
+    fn folds_bounces_back_into_the_field() {
+        // two bounces, off the top at 10 then the bottom at -10
+        assert_eq!(predict_intercept(Vec2::ZERO, Vec2::new(1., 1.), 35., -10., 10.), Some(-5.));
+        // one open side only bounces once
+        assert_eq!(predict_intercept(Vec2::ZERO, Vec2::new(1., 1.), 35., f32::NEG_INFINITY, 10.), Some(-15.));
+    }
+
+    #[test]
+    fn prediction_matches_rapier_in_versus() {
+        let (predicted, actual) = predicted_and_actual(None, Vec2::new(-300., 0.), Vec2::new(300., 400.));
+        assert!((predicted - actual).abs() < 5., "predicted {predicted} but the ball got to {actual}");
+    }
+
+    #[test]
+    fn prediction_matches_rapier_between_layout_walls() {
+        let layout: ArenaLayout = ron::from_str(NARROW).expect("test layout parses");
+        let (predicted, actual) = predicted_and_actual(Some(layout), Vec2::new(-300., 0.), Vec2::new(300., 400.));
+        assert!((predicted - actual).abs() < 5., "predicted {predicted} but the ball got to {actual}");
+    }
+
+    /// plays back `script` one tick at a time, holding its last control
+    struct ScriptedAi {
+        script: Vec<AiControl>,
+        control: AiControl,
+    }
+
+    impl PongAi for ScriptedAi {
+        fn control(&self) -> AiControl {
+            self.control
+        }
+        fn update(&mut self, _world: &mut World, _player: Player) {
+            if !self.script.is_empty() {
+                self.control = self.script.remove(0);
+            }
+        }
+    }
+
+    /// what a tuned ai around `script` does over `ticks`
+    fn tuned(script: Vec<AiControl>, tuning: AiTuning, ticks: usize) -> Vec<AiControl> {
+        let mut world = World::new();
+        world.insert_resource(GameRng::new(0));
+        let mut brain = AiBrain(Box::new(ScriptedAi { script, control: AiControl::default() })).with_difficulty(tuning);
+        (0..ticks).map(|_| {
+            brain.update(&mut world, Player::PlayerOne);
+            brain.control()
+        }).collect()
+    }
+
+    const STEADY: AiTuning = AiTuning { reaction_frames: 5, aim_error: 0., max_speed: 1., hesitation: 0. };
+
+    #[test]
+    fn reaction_delay_holds_for_reaction_frames() {
+        let script = [AiControl::Slide(1.); 10].into_iter().chain([AiControl::Slide(-1.); 10]).collect();
+        let controls = tuned(script, STEADY, 20);
+        assert_eq!(controls[..5], [AiControl::Slide(0.); 5]);
+        assert_eq!(controls[5..15], [AiControl::Slide(1.); 10]);
+        assert_eq!(controls[15..], [AiControl::Slide(-1.); 5]);
+    }
+
+    #[test]
+    fn no_reaction_frames_reacts_straight_away() {
+        let controls = tuned(vec![AiControl::Slide(1.)], AiTuning { reaction_frames: 0, ..STEADY }, 1);
+        assert_eq!(controls, [AiControl::Slide(1.)]);
+    }
+
+    #[test]
+    fn output_is_clamped_to_max_speed() {
+        let tuning = AiTuning { reaction_frames: 0, max_speed: 0.5, ..STEADY };
+        let script = vec![AiControl::Slide(1.), AiControl::Slide(-1.), AiControl::Slide(0.3), AiControl::Turn(0.9), AiControl::Turn(-0.9)];
+        let controls = tuned(script, tuning, 5);
+        assert_eq!(controls, [AiControl::Slide(0.5), AiControl::Slide(-0.5), AiControl::Slide(0.3), AiControl::Turn(0.5), AiControl::Turn(-0.5)]);
+    }
+
+    #[test]
+    fn custom_tuning_stays_in_range() {
+        let mut settings = AiSettings::default();
+        settings.adjust(|custom| custom.max_speed += 0.05);
+        assert_eq!(settings.difficulty, AiDifficulty::Custom);
+        assert_eq!(settings.custom.max_speed, 0.85);
+        settings.adjust(|custom| {custom.max_speed = 3.; custom.hesitation = -1.; custom.reaction_frames = 100;});
+        assert_eq!((settings.custom.max_speed, settings.custom.hesitation, settings.custom.reaction_frames), (1., 0., 60));
+    }
+
+    #[test]
+    fn earth_inside_the_orbit_crosses_on_the_way_out() {
+        assert_eq!(predict_crossing(Vec2::ZERO, Vec2::new(10., 0.), 100.), Some(10.));
+        assert_eq!(predict_crossing(Vec2::new(50., 0.), Vec2::new(10., 0.), 100.), Some(5.));
+        // heading for the middle it goes through and out the far side
+        assert_eq!(predict_crossing(Vec2::new(50., 0.), Vec2::new(-10., 0.), 100.), Some(15.));
+    }
+
+    #[test]
+    fn earth_outside_the_orbit() {
+        assert_eq!(predict_crossing(Vec2::new(-200., 0.), Vec2::new(10., 0.), 100.), Some(10.));
+        assert_eq!(predict_crossing(Vec2::new(200., 0.), Vec2::new(10., 0.), 100.), None);
+    }
+
+    #[test]
+    fn still_earth_never_crosses() {
+        assert_eq!(predict_crossing(Vec2::new(50., 0.), Vec2::ZERO, 100.), None);
+    }
+
+    #[test]
+    fn earth_passing_wide_never_crosses() {
+        assert_eq!(predict_crossing(Vec2::new(-300., 200.), Vec2::new(10., 0.), 100.), None);
+    }
+
+    /// which way the orbit ai turns a moon sat at angle 0 for an earth heading out from the middle at `angle`
+    fn orbit_turn(angle: Option<f32>) -> f32 {
+        let mut world = World::new();
+        world.spawn((Player::PlayerOne, Transform::from_xyz(250., 0., 0.), Paddle { size: Vec2::new(25., 100.), ..Default::default() }));
+        if let Some(angle) = angle {
+            world.spawn((Ball, Transform::default(), Velocity::linear(Vec2::from_angle(angle) * 100.), Collider::ball(25.)));
+        }
+        let mut ai = OrbitPongAi { turn: 0., aim: 0. };
+        ai.update(&mut world, Player::PlayerOne);
+        ai.control().turn()
+    }
+
+    #[test]
+    fn orbit_ai_turns_towards_the_earth() {
+        assert_eq!(orbit_turn(Some(0.5)), 1., "should go anticlockwise to meet it");
+        assert_eq!(orbit_turn(Some(-0.5)), -1., "should go clockwise to meet it");
+        // close to the target it eases off
+        assert!((orbit_turn(Some(0.1)) - 0.5).abs() < 1e-3);
+        assert!((orbit_turn(Some(-0.1)) + 0.5).abs() < 1e-3);
+        assert_eq!(orbit_turn(Some(0.)), 0.);
+        assert_eq!(orbit_turn(None), 0.);
+    }
+}
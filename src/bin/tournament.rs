@@ -0,0 +1,135 @@
+//! plays every registered ai against every other without a window and reports how they did
+//!
+//! `cargo run --release --bin tournament -- --matches 20 --format csv --out results.csv`
+
+use std::io::Write;
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use bevy_pong::{GameState, Player, ai::{AiTuning, SeatKind, SeatSelection}, game::{headless_app, MatchResult, MatchRules, MatchSeed, Rally}};
+
+/// a match that hasn't finished after this many ticks is called a draw
+const MAX_TICKS: u32 = 60 * 60 * 10;
+const ARENA: Vec2 = Vec2::new(1280., 720.);
+
+struct Options {
+    matches: u32,
+    points: u16,
+    csv: bool,
+    out: Option<String>,
+}
+
+impl Options {
+    fn from_args() -> Options {
+        let mut options = Options { matches: 10, points: 11, csv: false, out: None };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--matches" => options.matches = args.next().and_then(|n| n.parse().ok()).expect("--matches takes a number"),
+                "--points" => options.points = args.next().and_then(|n| n.parse().ok()).expect("--points takes a number"),
+                "--format" => options.csv = match args.next().as_deref() {
+                    Some("csv") => true,
+                    Some("json") => false,
+                    other => panic!("unknown format {:?}, expected json or csv", other),
+                },
+                "--out" => options.out = args.next(),
+                other => panic!("unknown argument {other}"),
+            }
+        }
+        options
+    }
+}
+
+struct MatchOutcome {
+    winner: Option<Player>,
+    points: u32,
+    hits: u32,
+}
+
+fn play_match(left: SeatKind, right: SeatKind, points: u16, seed: u64) -> MatchOutcome {
+    let mut app = headless_app(ARENA);
+    app.insert_resource(MatchSeed(Some(seed)));
+    let seats = SeatSelection { player1: left, player2: right, ..Default::default() };
+    app.insert_resource(seats.brains(AiTuning::HARD));
+    app.insert_resource(MatchRules { points_to_win: points, win_by_two: true, time_limit: None, ..Default::default() });
+    app.world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+
+    for _ in 0..MAX_TICKS {
+        app.update();
+        if GameState::GameOver.eq(app.world.resource::<State<GameState>>().get()) {
+            let result = app.world.resource::<MatchResult>();
+            return MatchOutcome {
+                winner: result.winner,
+                points: (result.score.0 + result.score.1) as u32,
+                hits: app.world.resource::<Rally>().total_hits,
+            };
+        }
+    }
+    let score = app.world.resource::<bevy_pong::game::Score>();
+    MatchOutcome { winner: None, points: (score.0 + score.1) as u32, hits: app.world.resource::<Rally>().total_hits }
+}
+
+#[derive(Serialize)]
+struct PairReport {
+    ai: String,
+    opponent: String,
+    matches: u32,
+    wins: u32,
+    losses: u32,
+    draws: u32,
+    win_rate: f32,
+    average_rally: f32,
+    points_per_match: f32,
+}
+
+fn main() {
+    let options = Options::from_args();
+    let mut reports = Vec::new();
+    for ai in SeatKind::AI {
+        for opponent in SeatKind::AI {
+            let (mut wins, mut losses, mut draws, mut points, mut hits) = (0, 0, 0, 0, 0);
+            for game in 0..options.matches {
+                // swap sides every game so neither ai always serves first
+                let ai_side = if game % 2 == 0 {Player::PlayerOne} else {Player::PlayerTwo};
+                let outcome = match ai_side {
+                    Player::PlayerOne => play_match(ai, opponent, options.points, game as u64),
+                    Player::PlayerTwo => play_match(opponent, ai, options.points, game as u64),
+                };
+                match outcome.winner {
+                    Some(winner) if winner == ai_side => wins += 1,
+                    Some(_) => losses += 1,
+                    None => draws += 1,
+                }
+                points += outcome.points;
+                hits += outcome.hits;
+            }
+            eprintln!("{:?} vs {:?}: {} - {} ({} draws)", ai, opponent, wins, losses, draws);
+            reports.push(PairReport {
+                ai: format!("{:?}", ai),
+                opponent: format!("{:?}", opponent),
+                matches: options.matches,
+                wins,
+                losses,
+                draws,
+                win_rate: wins as f32 / options.matches.max(1) as f32,
+                average_rally: hits as f32 / points.max(1) as f32,
+                points_per_match: points as f32 / options.matches.max(1) as f32,
+            });
+        }
+    }
+
+    let output = if options.csv {
+        let mut csv = String::from("ai,opponent,matches,wins,losses,draws,win_rate,average_rally,points_per_match\n");
+        for r in &reports {
+            csv.push_str(&format!("{},{},{},{},{},{},{},{},{}\n", r.ai, r.opponent, r.matches, r.wins, r.losses, r.draws, r.win_rate, r.average_rally, r.points_per_match));
+        }
+        csv
+    } else {
+        serde_json::to_string_pretty(&reports).expect("report is always serializable")
+    };
+    match options.out {
+        Some(path) => std::fs::write(&path, output).unwrap_or_else(|e| panic!("failed to write {path}: {e}")),
+        None => std::io::stdout().write_all(output.as_bytes()).expect("failed to write to stdout"),
+    }
+}
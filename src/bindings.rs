@@ -0,0 +1,462 @@
+use bevy::{prelude::*, ecs::system::SystemParam, window::PrimaryWindow};
+use serde::{Serialize, Deserialize};
+
+use crate::{GameCamera, Player::{self, *}};
+
+/// how many keys can be bound to a single action
+pub const KEY_SLOTS: usize = 2;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+}
+
+impl Action {
+    /// every action a player can bind, new actions need adding here
+    pub const ALL: [Action; 2] = [Action::MoveUp, Action::MoveDown];
+}
+
+/// one key slot of one action for one player
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub player: Player,
+    pub action: Action,
+    pub slot: usize,
+}
+
+impl Binding {
+    pub fn new(player: Player, action: Action, slot: usize) -> Binding {
+        Binding { player, action, slot }
+    }
+
+    fn all() -> impl Iterator<Item = Binding> {
+        Player::ALL.into_iter().flat_map(|player| {
+            Action::ALL.into_iter().flat_map(move |action| {
+                (0..KEY_SLOTS).map(move |slot| Binding::new(player, action, slot))
+            })
+        })
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug)]
+pub struct PlayerKeyBinds {
+    pub player1: KeyBindings,
+    pub player2: KeyBindings,
+    #[serde(default = "KeyBindings::player3")]
+    pub player3: KeyBindings,
+    #[serde(default = "KeyBindings::player4")]
+    pub player4: KeyBindings,
+}
+
+impl PlayerKeyBinds {
+    /// W/S for player one, the arrow keys for player two, I/K for three and the numpad for four
+    pub fn standard() -> PlayerKeyBinds {
+        PlayerKeyBinds {
+            player1: KeyBindings::new(KeyCode::W, KeyCode::S),
+            player2: KeyBindings::new(KeyCode::Up, KeyCode::Down),
+            player3: KeyBindings::player3(),
+            player4: KeyBindings::player4(),
+        }
+    }
+
+    /// binds `to` into `binding`, whatever else was on `to` is given the key `binding` had
+    pub fn set(&mut self, binding: Binding, to: KeyCode) {
+        let old = self.key(binding).filter(|old| *old != to);
+        // a save that already clashes could have `old` somewhere else too, handing it on would just move the clash
+        let old_elsewhere = old.is_some() && Binding::all().any(|other| other != binding && self.key(other) == old);
+        let mut swap = if old_elsewhere {None} else {old};
+        for other in Binding::all() {
+            if other == binding {continue;}
+            if self.key(other) == Some(to) {
+                // only one slot can take the old key, any more that were already on `to` are cleared
+                *self.key_mut(other) = swap.take();
+            }
+        }
+        *self.key_mut(binding) = Some(to);
+        info!("Set {:?} to {:?}", binding, to);
+    }
+
+    pub fn clear(&mut self, binding: Binding) {
+        *self.key_mut(binding) = None;
+    }
+
+    pub fn key(&self, binding: Binding) -> Option<KeyCode> {
+        self.get(binding.player).keys(binding.action).0[binding.slot]
+    }
+
+    fn key_mut(&mut self, binding: Binding) -> &mut Option<KeyCode> {
+        &mut self.get_mut(binding.player).keys_mut(binding.action).0[binding.slot]
+    }
+
+    /// every pair of slots sharing a key
+    pub fn conflicts(&self) -> Vec<(Binding, Binding)> {
+        let all: Vec<Binding> = Binding::all().collect();
+        let mut conflicts = Vec::new();
+        for (i, a) in all.iter().enumerate() {
+            let Some(key) = self.key(*a) else {continue;};
+            for b in &all[i + 1..] {
+                if self.key(*b) == Some(key) {
+                    conflicts.push((*a, *b));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// actions with no key in any slot
+    pub fn unbound(&self) -> Vec<(Player, Action)> {
+        let mut unbound = Vec::new();
+        for player in Player::ALL {
+            for action in Action::ALL {
+                if self.get(player).keys(action).0.iter().all(Option::is_none) {
+                    unbound.push((player, action));
+                }
+            }
+        }
+        unbound
+    }
+
+    /// binds a gamepad button, swapping with any other action on the same gamepad that used it
+    pub fn set_pad_button(&mut self, player: Player, action: Action, to: GamepadButtonType) {
+        let old = self.get(player).gamepad.button(action);
+        let pad = self.get(player).gamepad.gamepad;
+        for other_player in Player::ALL {
+            let other_pad = self.get(other_player).gamepad.gamepad;
+            if other_player != player && (pad.is_none() || other_pad != pad) {continue;}
+            for other in Action::ALL {
+                if other_player == player && other == action {continue;}
+                let button = self.get_mut(other_player).gamepad.button_mut(other);
+                if *button == to {
+                    *button = old;
+                }
+            }
+        }
+        *self.get_mut(player).gamepad.button_mut(action) = to;
+    }
+
+    pub fn get(&self, player: Player) -> &KeyBindings {
+        match player {
+            PlayerOne => &self.player1,
+            PlayerTwo => &self.player2,
+            PlayerThree => &self.player3,
+            PlayerFour => &self.player4,
+        }
+    }
+
+    pub fn get_mut(&mut self, player: Player) -> &mut KeyBindings {
+        match player {
+            PlayerOne => &mut self.player1,
+            PlayerTwo => &mut self.player2,
+            PlayerThree => &mut self.player3,
+            PlayerFour => &mut self.player4,
+        }
+    }
+}
+
+impl FromWorld for PlayerKeyBinds {
+    fn from_world(world: &mut World) -> Self {
+        let pkv = world.resource::<bevy_pkv::PkvStore>();
+        if let Ok(bindings) = pkv.get("KeyBind") {
+            bindings
+        } else {
+            PlayerKeyBinds::standard()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct KeyBindings {
+    pub move_up: KeySlots,
+    pub move_down: KeySlots,
+    #[serde(default)]
+    pub gamepad: GamepadBindings,
+    #[serde(default)]
+    pub control: ControlScheme,
+}
+
+impl KeyBindings {
+    fn new(up: KeyCode, down: KeyCode) -> KeyBindings {
+        KeyBindings {
+            move_up: KeySlots::single(up),
+            move_down: KeySlots::single(down),
+            gamepad: GamepadBindings::default(),
+            control: ControlScheme::Buttons,
+        }
+    }
+
+    fn player3() -> KeyBindings {
+        KeyBindings::new(KeyCode::I, KeyCode::K)
+    }
+
+    fn player4() -> KeyBindings {
+        KeyBindings::new(KeyCode::Numpad8, KeyCode::Numpad5)
+    }
+
+    pub fn keys(&self, action: Action) -> &KeySlots {
+        match action {
+            Action::MoveUp => &self.move_up,
+            Action::MoveDown => &self.move_down,
+        }
+    }
+
+    fn keys_mut(&mut self, action: Action) -> &mut KeySlots {
+        match action {
+            Action::MoveUp => &mut self.move_up,
+            Action::MoveDown => &mut self.move_down,
+        }
+    }
+}
+
+/// the keys bound to one action, older saves with a single key still load
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(from = "KeySlotsRepr")]
+pub struct KeySlots(pub [Option<KeyCode>; KEY_SLOTS]);
+
+impl KeySlots {
+    fn single(key: KeyCode) -> KeySlots {
+        let mut slots = [None; KEY_SLOTS];
+        slots[0] = Some(key);
+        KeySlots(slots)
+    }
+
+    pub fn pressed(&self, input: &Input<KeyCode>) -> bool {
+        self.0.iter().flatten().any(|key| input.pressed(*key))
+    }
+}
+
+impl std::fmt::Display for KeySlots {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, key) in self.0.iter().enumerate() {
+            if i > 0 {write!(f, " / ")?;}
+            match key {
+                Some(key) => write!(f, "{:?}", key)?,
+                None => write!(f, "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeySlotsRepr {
+    Single(KeyCode),
+    Slots([Option<KeyCode>; KEY_SLOTS]),
+}
+
+impl From<KeySlotsRepr> for KeySlots {
+    fn from(value: KeySlotsRepr) -> Self {
+        match value {
+            KeySlotsRepr::Single(key) => KeySlots::single(key),
+            KeySlotsRepr::Slots(slots) => KeySlots(slots),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GamepadBindings {
+    /// id of the gamepad this player is using, `None` for keyboard only
+    pub gamepad: Option<usize>,
+    pub axis: GamepadAxisType,
+    pub move_up: GamepadButtonType,
+    pub move_down: GamepadButtonType,
+}
+
+impl GamepadBindings {
+    pub fn button(&self, action: Action) -> GamepadButtonType {
+        match action {
+            Action::MoveUp => self.move_up,
+            Action::MoveDown => self.move_down,
+        }
+    }
+
+    fn button_mut(&mut self, action: Action) -> &mut GamepadButtonType {
+        match action {
+            Action::MoveUp => &mut self.move_up,
+            Action::MoveDown => &mut self.move_down,
+        }
+    }
+}
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        GamepadBindings {
+            gamepad: None,
+            axis: GamepadAxisType::LeftStickY,
+            move_up: GamepadButtonType::DPadUp,
+            move_down: GamepadButtonType::DPadDown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControlScheme {
+    /// keyboard and gamepad
+    #[default]
+    Buttons,
+    /// paddle follows the mouse or a touch
+    Pointer,
+}
+
+impl ControlScheme {
+    pub fn next(self) -> ControlScheme {
+        match self {
+            ControlScheme::Buttons => ControlScheme::Pointer,
+            ControlScheme::Pointer => ControlScheme::Buttons,
+        }
+    }
+}
+
+/// everything needed to turn a player's bindings into a paddle delta
+#[derive(SystemParam)]
+pub struct PlayerInput<'w, 's> {
+    bindings: Res<'w, PlayerKeyBinds>,
+    keys: Res<'w, Input<KeyCode>>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    touches: Res<'w, Touches>,
+    window: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    camera: Query<'w, 's, (&'static Camera, &'static GlobalTransform), With<GameCamera>>,
+}
+
+impl PlayerInput<'_, '_> {
+    pub fn control(&self, player: Player) -> ControlScheme {
+        self.bindings.get(player).control
+    }
+
+    /// world position of the first touch, or the mouse if nothing is touching the screen
+    pub fn pointer(&self) -> Option<Vec2> {
+        let (camera, transform) = self.camera.get_single().ok()?;
+        let screen = match self.touches.iter().next() {
+            Some(touch) => touch.position(),
+            None => self.window.get_single().ok()?.cursor_position()?,
+        };
+        camera.viewport_to_world_2d(transform, screen)
+    }
+
+    /// how hard the player is pushing up, from -1 (full down) to 1 (full up)
+    pub fn delta(&self, player: Player) -> f32 {
+        let bindings = self.bindings.get(player);
+        let mut delta = 0.0;
+        if bindings.move_up.pressed(&self.keys) {delta += 1.;}
+        if bindings.move_down.pressed(&self.keys) {delta -= 1.;}
+        if let Some(id) = bindings.gamepad.gamepad {
+            let gamepad = Gamepad::new(id);
+            if self.buttons.pressed(GamepadButton::new(gamepad, bindings.gamepad.move_up)) {delta += 1.;}
+            if self.buttons.pressed(GamepadButton::new(gamepad, bindings.gamepad.move_down)) {delta -= 1.;}
+            delta += self.axes.get(GamepadAxis::new(gamepad, bindings.gamepad.axis)).unwrap_or(0.);
+        }
+        delta.clamp(-1., 1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind(player: Player, action: Action, slot: usize) -> Binding {
+        Binding::new(player, action, slot)
+    }
+
+    #[test]
+    fn swaps_primary_slots_of_one_player() {
+        let mut bindings = PlayerKeyBinds::standard();
+        bindings.set(bind(PlayerOne, Action::MoveUp, 0), KeyCode::S);
+        assert_eq!(bindings.player1.move_up.0, [Some(KeyCode::S), None]);
+        assert_eq!(bindings.player1.move_down.0, [Some(KeyCode::W), None]);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn swaps_primary_slots_across_players() {
+        let mut bindings = PlayerKeyBinds::standard();
+        bindings.set(bind(PlayerOne, Action::MoveUp, 0), KeyCode::Up);
+        assert_eq!(bindings.player1.move_up.0, [Some(KeyCode::Up), None]);
+        assert_eq!(bindings.player2.move_up.0, [Some(KeyCode::W), None]);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn empty_alt_slot_takes_key_from_same_player() {
+        let mut bindings = PlayerKeyBinds::standard();
+        bindings.set(bind(PlayerOne, Action::MoveUp, 1), KeyCode::S);
+        assert_eq!(bindings.player1.move_up.0, [Some(KeyCode::W), Some(KeyCode::S)]);
+        assert_eq!(bindings.player1.move_down.0, [None, None]);
+        assert_eq!(bindings.unbound(), vec![(PlayerOne, Action::MoveDown)]);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn alt_slot_swaps_with_other_players_alt_slot() {
+        let mut bindings = PlayerKeyBinds::standard();
+        bindings.set(bind(PlayerOne, Action::MoveUp, 1), KeyCode::Q);
+        bindings.set(bind(PlayerTwo, Action::MoveDown, 1), KeyCode::E);
+        bindings.set(bind(PlayerTwo, Action::MoveDown, 1), KeyCode::Q);
+        assert_eq!(bindings.player2.move_down.0, [Some(KeyCode::Down), Some(KeyCode::Q)]);
+        assert_eq!(bindings.player1.move_up.0, [Some(KeyCode::W), Some(KeyCode::E)]);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn alt_slot_swaps_with_primary_slot() {
+        let mut bindings = PlayerKeyBinds::standard();
+        bindings.set(bind(PlayerThree, Action::MoveDown, 1), KeyCode::Q);
+        bindings.set(bind(PlayerThree, Action::MoveDown, 1), KeyCode::Numpad8);
+        assert_eq!(bindings.player3.move_down.0, [Some(KeyCode::K), Some(KeyCode::Numpad8)]);
+        assert_eq!(bindings.player4.move_up.0, [Some(KeyCode::Q), None]);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn unbound_key_changes_only_that_slot() {
+        let mut bindings = PlayerKeyBinds::standard();
+        bindings.set(bind(PlayerFour, Action::MoveDown, 0), KeyCode::Z);
+        assert_eq!(bindings.player4.move_down.0, [Some(KeyCode::Z), None]);
+        assert_eq!(bindings.player4.move_up.0, [Some(KeyCode::Numpad8), None]);
+        assert_eq!(bindings.player1.move_up.0, [Some(KeyCode::W), None]);
+        assert!(bindings.conflicts().is_empty());
+        assert!(bindings.unbound().is_empty());
+    }
+
+    #[test]
+    fn same_key_again_changes_nothing() {
+        let mut bindings = PlayerKeyBinds::standard();
+        bindings.set(bind(PlayerOne, Action::MoveUp, 0), KeyCode::W);
+        assert_eq!(bindings.player1.move_up.0, [Some(KeyCode::W), None]);
+        assert_eq!(bindings.player1.move_down.0, [Some(KeyCode::S), None]);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn existing_clash_on_new_key_is_not_duplicated() {
+        let mut bindings = PlayerKeyBinds::standard();
+        *bindings.key_mut(bind(PlayerTwo, Action::MoveUp, 0)) = Some(KeyCode::W);
+        *bindings.key_mut(bind(PlayerThree, Action::MoveUp, 0)) = Some(KeyCode::W);
+        bindings.set(bind(PlayerFour, Action::MoveUp, 0), KeyCode::W);
+        assert_eq!(bindings.player4.move_up.0, [Some(KeyCode::W), None]);
+        assert_eq!(bindings.player1.move_up.0, [Some(KeyCode::Numpad8), None]);
+        assert_eq!(bindings.player2.move_up.0, [None, None]);
+        assert_eq!(bindings.player3.move_up.0, [None, None]);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn existing_clash_on_old_key_is_not_handed_on() {
+        let mut bindings = PlayerKeyBinds::standard();
+        *bindings.key_mut(bind(PlayerTwo, Action::MoveUp, 1)) = Some(KeyCode::W);
+        bindings.set(bind(PlayerOne, Action::MoveUp, 0), KeyCode::S);
+        assert_eq!(bindings.player1.move_down.0, [None, None]);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn same_key_again_clears_an_existing_clash() {
+        let mut bindings = PlayerKeyBinds::standard();
+        *bindings.key_mut(bind(PlayerTwo, Action::MoveUp, 1)) = Some(KeyCode::W);
+        bindings.set(bind(PlayerOne, Action::MoveUp, 0), KeyCode::W);
+        assert_eq!(bindings.player1.move_up.0, [Some(KeyCode::W), None]);
+        assert_eq!(bindings.player2.move_up.0, [Some(KeyCode::Up), None]);
+        assert!(bindings.conflicts().is_empty());
+    }
+}
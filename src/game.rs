@@ -1,8 +1,24 @@
 mod normal;
 mod orbit;
+mod rules;
+mod replay;
+mod net;
+mod spectate;
+mod powerup;
+mod layout;
+mod highscore;
 
-use bevy::prelude::*;
+pub use normal::start_attract_mode;
+pub use rules::{MatchRules, MatchResult};
+pub use layout::{ArenaLayout, SelectedLayout};
+pub use net::NetSession;
+
+use bevy::{prelude::*, render::camera::ScalingMode};
 use bevy_rapier2d::prelude::*;
+use rand::{SeedableRng, rngs::StdRng};
+use serde::{Serialize, Deserialize};
+
+use crate::{GameState, Paused, Player};
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
@@ -10,35 +26,221 @@ impl Plugin for GamePlugin {
         app
         .init_resource::<Score>()
         .register_type::<Score>()
-        .add_plugins(bevy_rapier2d::plugin::RapierPhysicsPlugin::<NoUserData>::default())
-        .add_plugins(bevy_rapier2d::prelude::RapierDebugRenderPlugin::default())
+        .init_resource::<Arena>()
+        .init_resource::<SelectedLayout>()
+        .init_resource::<Rally>()
+        .init_resource::<BallIds>()
+        .init_resource::<PlayerNames>()
+        .insert_resource(FixedTime::new_from_secs(TICK))
+        .init_resource::<GameRng>()
+        .init_resource::<MatchSeed>()
+        .add_plugins(bevy_rapier2d::plugin::RapierPhysicsPlugin::<NoUserData>::default().in_fixed_schedule())
+        .insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed { dt: TICK, substeps: 1 },
+            ..Default::default()
+        })
+        .add_systems(OnEnter(GameState::PlayingNormal), seed_match)
+        .add_systems(OnEnter(GameState::PlayingOrbit), seed_match)
         .add_plugins(normal::NormalPlugin)
-        .add_plugins(orbit::OrbitPlugin)
-        .add_systems(Update, clean_up_lifetime)
+        .add_plugins(rules::RulesPlugin)
+        .add_plugins(powerup::PowerUpPlugin)
+        .add_systems(FixedUpdate, clean_up_lifetime.run_if(in_state(Paused::Running)))
+        .add_systems(Update, pause_physics.run_if(state_changed::<Paused>()))
+        .add_systems(OnExit(GameState::PlayingNormal), unpause)
+        .add_systems(OnExit(GameState::PlayingOrbit), unpause)
+        .add_systems(OnEnter(GameState::Restarting), restart_game)
         .register_type::<Paddle>();
+
+        if app.world.contains_resource::<Headless>() {
+            // nobody is going to rebind keys without a window, so skip the save file
+            app.insert_resource(crate::bindings::PlayerKeyBinds::standard());
+            return;
+        }
+        app
+        .add_plugins(bevy_rapier2d::prelude::RapierDebugRenderPlugin::default())
+        .add_plugins(orbit::OrbitPlugin)
+        .add_plugins(replay::ReplayPlugin)
+        .add_plugins(net::NetPlugin)
+        .add_plugins(spectate::SpectatePlugin)
+        .add_plugins(layout::LayoutPlugin)
+        .add_plugins(highscore::HighScorePlugin)
+        .add_systems(PostUpdate, fit_camera.run_if(resource_changed::<Arena>()));
+    }
+}
+
+/// insert before adding [`GamePlugin`] to run without a window, renderer or ui,
+/// only normal mode is available and [`Arena`] has to be set by hand
+#[derive(Resource)]
+pub struct Headless;
+
+/// size of the play area in world units, it stays the same whatever the window does
+/// and the camera scales it to fit with bars down the sides or along the top and bottom
+#[derive(Debug, Resource, Clone, Copy)]
+pub struct Arena {
+    pub size: Vec2,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena { size: Vec2::new(1280., 720.) }
+    }
+}
+
+/// only the game's own camera, the editor brings cameras of its own
+fn fit_camera(
+    arena: Res<Arena>,
+    mut cameras: Query<&mut OrthographicProjection, With<crate::GameCamera>>,
+) {
+    for mut projection in &mut cameras {
+        projection.scaling_mode = ScalingMode::AutoMin { min_width: arena.size.x, min_height: arena.size.y };
+    }
+}
+
+/// shown to spectators and on the game over screen
+#[derive(Debug, Resource, Clone)]
+pub struct PlayerNames(pub [String; 4]);
+
+impl PlayerNames {
+    pub fn get(&self, player: Player) -> &str {
+        &self.0[player.index()]
+    }
+}
+
+impl Default for PlayerNames {
+    fn default() -> Self {
+        PlayerNames(["Player One", "Player Two", "Player Three", "Player Four"].map(String::from))
     }
 }
 
+/// paddle hits in the current point and across the whole match
+#[derive(Debug, Resource, Default)]
+pub struct Rally {
+    pub hits: u32,
+    pub total_hits: u32,
+}
+
 #[derive(Component)]
 struct GameItem;
 
+/// length of one gameplay tick in seconds, paddles, ai and physics all step at this rate
+pub const TICK: f32 = 1. / 60.;
+
+/// every bit of gameplay randomness comes from here so a match can be replayed from its seed
+#[derive(Resource, Clone)]
+pub struct GameRng {
+    seed: u64,
+    rng: StdRng,
+    ai: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> GameRng {
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            ai: StdRng::seed_from_u64(seed ^ 0x9E37_79B9_7F4A_7C15),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    /// kept apart from [`GameRng::rng`] so replays, which don't run the ais, spawn the same balls
+    pub fn ai_rng(&mut self) -> &mut StdRng {
+        &mut self.ai
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::new(0)
+    }
+}
+
+/// seed to use for the next match, a random one is picked when `None`
+#[derive(Resource, Default)]
+pub struct MatchSeed(pub Option<u64>);
+
+fn seed_match(
+    requested: Res<MatchSeed>,
+    mut rng: ResMut<GameRng>,
+) {
+    let seed = requested.0.unwrap_or_else(rand::random);
+    info!("Match seed: {seed}");
+    *rng = GameRng::new(seed);
+}
+
+/// thickness of the top and bottom walls in normal mode
+pub const WALL_THICKNESS: f32 = 10.;
+
 #[derive(Debug, Component, Reflect, Default)]
 #[reflect(Component)]
 pub struct Paddle {
-    size: Vec2,
-    speed: f32,
+    pub size: Vec2,
+    pub speed: f32,
+    /// slides left and right instead of up and down
+    pub horizontal: bool,
+    /// furthest the paddle's centre can get from the middle of its lane
+    pub travel: f32,
+    /// how fast it really moved along its lane last tick, after flipped controls and the ends of the lane
+    pub velocity: f32,
 }
 
 #[derive(Component)]
 pub struct Ball;
 
-#[derive(Debug, Resource, Default, Reflect)]
+/// tells balls apart when rolling back, handed out in spawn order by [`BallIds`]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BallId(pub u32);
+
+/// the id the next ball gets, starts again every match
+#[derive(Resource, Default, Clone, Copy)]
+pub struct BallIds(u32);
+
+impl BallIds {
+    pub fn next(&mut self) -> BallId {
+        self.0 += 1;
+        BallId(self.0)
+    }
+}
+
+/// points for each seat, or lives left in four player mode
+#[derive(Debug, Resource, Default, Reflect, Clone, Copy, Serialize, Deserialize)]
 #[reflect(Resource)]
-struct Score(u8, u8);
+pub struct Score(pub u16, pub u16, pub u16, pub u16);
+
+impl Score {
+    pub fn get(&self, player: Player) -> u16 {
+        match player {
+            Player::PlayerOne => self.0,
+            Player::PlayerTwo => self.1,
+            Player::PlayerThree => self.2,
+            Player::PlayerFour => self.3,
+        }
+    }
+
+    pub fn get_mut(&mut self, player: Player) -> &mut u16 {
+        match player {
+            Player::PlayerOne => &mut self.0,
+            Player::PlayerTwo => &mut self.1,
+            Player::PlayerThree => &mut self.2,
+            Player::PlayerFour => &mut self.3,
+        }
+    }
+}
 
 #[derive(Component)]
 struct Goal;
 
+/// anything solid the ball bounces off that isn't a paddle
+#[derive(Component)]
+pub struct Wall;
+
 fn clean_up_game(
     query: Query<Entity, With<GameItem>>,
     mut commands: Commands,
@@ -48,18 +250,95 @@ fn clean_up_game(
     }
 }
 
+/// seconds left before the entity is despawned, counted in gameplay ticks so replays agree
 #[derive(Component)]
 struct Lifetime(f32);
 
 fn clean_up_lifetime(
     mut commands: Commands,
-    time: Res<Time>,
+    time: Res<FixedTime>,
     mut query: Query<(Entity, &mut Lifetime)>,
 ) {
     for (entity, mut life) in &mut query {
-        life.0 -= time.delta_seconds();
+        life.0 -= time.period.as_secs_f32();
         if life.0 < 0. {
             commands.entity(entity).despawn_recursive();
         }
     }
-}
\ No newline at end of file
+}
+
+fn pause_physics(
+    paused: Res<State<Paused>>,
+    mut config: ResMut<RapierConfiguration>,
+) {
+    config.physics_pipeline_active = Paused::Running.eq(paused.get());
+}
+
+fn unpause(
+    mut next: ResMut<NextState<Paused>>,
+) {
+    next.set(Paused::Running);
+}
+
+/// a windowless app for the tournament runner and tests that steps one gameplay tick per update,
+/// set [`MatchRules`], [`MatchSeed`] and anything else needed then move it into [`GameState::PlayingNormal`]
+pub fn headless_app(arena: Vec2) -> App {
+    use bevy::{time::TimeUpdateStrategy, input::InputPlugin, hierarchy::HierarchyPlugin, transform::TransformPlugin};
+    let mut app = App::new();
+    app
+    .insert_resource(Headless)
+    .add_plugins(MinimalPlugins)
+    .add_plugins((TransformPlugin, HierarchyPlugin, InputPlugin, AssetPlugin::default()))
+    .add_asset::<Mesh>()
+    .add_asset::<Image>()
+    .insert_resource(TimeUpdateStrategy::ManualDuration(std::time::Duration::from_secs_f32(TICK)))
+    .insert_resource(Arena { size: arena })
+    .insert_resource(MatchSeed(Some(0)))
+    .add_state::<GameState>()
+    .add_state::<Paused>()
+    .add_plugins(GamePlugin)
+    .add_plugins(crate::ai::AiPlugin);
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// the state to go back into after passing through [`GameState::Restarting`]
+#[derive(Resource)]
+pub struct RestartInto(pub GameState);
+
+fn restart_game(
+    restart: Res<RestartInto>,
+    mut next: ResMut<NextState<GameState>>,
+) {
+    next.set(restart.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::camera::CameraProjection;
+
+    use super::*;
+
+    #[test]
+    fn resized_window_still_shows_the_whole_arena() {
+        let mut world = World::new();
+        world.insert_resource(Arena { size: Vec2::new(1280., 720.) });
+        let game = world.spawn((Camera2dBundle::default(), crate::GameCamera)).id();
+        let editor = world.spawn(Camera2dBundle::default()).id();
+        let mut schedule = Schedule::new();
+        schedule.add_systems(fit_camera);
+        schedule.run(&mut world);
+
+        // a tall window, the usual shape and a very wide one
+        for (width, height) in [(600., 900.), (1920., 1080.), (2000., 500.)] {
+            let mut projection = world.get_mut::<OrthographicProjection>(game).expect("game camera has a projection");
+            projection.update(width, height);
+            let area = projection.area;
+            assert!(area.min.x <= -640. && area.max.x >= 640., "{width}x{height} cuts off the sides: {area:?}");
+            assert!(area.min.y <= -360. && area.max.y >= 360., "{width}x{height} cuts off the top or bottom: {area:?}");
+        }
+        let projection = world.get::<OrthographicProjection>(editor).expect("editor camera has a projection");
+        assert!(matches!(projection.scaling_mode, ScalingMode::WindowSize(_)), "fit_camera touched a camera that isn't the game's");
+    }
+}
@@ -0,0 +1,240 @@
+use std::time::SystemTime;
+
+use bevy::prelude::*;
+use belly::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::GameState;
+
+use super::orbit::{OrbitControls, OrbitRules};
+
+const PKV_KEY: &str = "OrbitHighScores";
+const TABLE_SIZE: usize = 10;
+
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<HighScores>()
+        .add_systems(OnEnter(GameState::Leaderboard), spawn_leaderboard)
+        .add_systems(OnExit(GameState::Leaderboard), crate::menu::close_menu)
+        .add_systems(OnEnter(GameState::OrbitGameOver), spawn_record_entry)
+        .add_systems(OnExit(GameState::OrbitGameOver), save_record);
+    }
+}
+
+/// one finished solo orbit run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u16,
+    /// seconds since the unix epoch
+    pub date: u64,
+    pub controls: OrbitControls,
+}
+
+/// the best solo orbit runs on this machine, highest first
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    entries: Vec<HighScore>,
+    /// filled in on the name box next time a record is set
+    last_name: String,
+    #[serde(skip)]
+    pub best: u16,
+    #[serde(skip)]
+    table: String,
+}
+
+impl FromWorld for HighScores {
+    fn from_world(world: &mut World) -> Self {
+        let pkv = world.resource::<bevy_pkv::PkvStore>();
+        let mut scores: HighScores = pkv.get(PKV_KEY).unwrap_or_default();
+        scores.refresh();
+        scores
+    }
+}
+
+impl HighScores {
+    fn qualifies(&self, score: u16) -> bool {
+        score > 0 && (self.entries.len() < TABLE_SIZE || self.entries.iter().any(|entry| entry.score < score))
+    }
+
+    fn insert(&mut self, entry: HighScore) {
+        self.last_name = entry.name.clone();
+        // after any equal scores so the older run keeps its place
+        let at = self.entries.partition_point(|other| other.score >= entry.score);
+        self.entries.insert(at, entry);
+        self.entries.truncate(TABLE_SIZE);
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        self.best = self.entries.first().map_or(0, |entry| entry.score);
+        self.table = if self.entries.is_empty() {
+            "No Runs Yet".to_string()
+        } else {
+            self.entries.iter().enumerate()
+                .map(|(i, entry)| format!("{}. {}  {}  {}  {:?}", i + 1, entry.name, entry.score, date(entry.date), entry.controls))
+                .collect::<Vec<_>>().join("\n")
+        };
+    }
+}
+
+/// year-month-day from unix seconds, the calendar maths is from Howard Hinnant's `civil_from_days`
+fn date(secs: u64) -> String {
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 {mp + 3} else {mp - 9};
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year}-{month:02}-{day:02}")
+}
+
+/// a run that made the table and is waiting for a name
+#[derive(Resource)]
+struct PendingRecord {
+    score: u16,
+    controls: OrbitControls,
+    name: String,
+}
+
+/// the name box on the game over screen, gone once the record is saved
+#[derive(Component)]
+struct RecordEntry;
+
+/// called when a solo run ends, the game over screen asks for a name if it made the table
+pub fn finish_run(world: &mut World, score: u16) {
+    let scores = world.resource::<HighScores>();
+    if !scores.qualifies(score) {return;}
+    info!("New orbit record: {score}");
+    let name = scores.last_name.clone();
+    let controls = world.resource::<OrbitRules>().controls;
+    world.insert_resource(PendingRecord { score, controls, name });
+}
+
+/// also runs on leaving the game over screen so a record isn't lost by skipping the name,
+/// it's the only place the table is written back so loading it never saves
+fn save_record(world: &mut World) {
+    let Some(record) = world.remove_resource::<PendingRecord>() else {return;};
+    let name = record.name.trim();
+    let date = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or_default();
+    world.resource_mut::<HighScores>().insert(HighScore {
+        name: if name.is_empty() {"Player".to_string()} else {name.to_string()},
+        score: record.score,
+        date,
+        controls: record.controls,
+    });
+    world.resource_scope(|world, mut pkv: Mut<bevy_pkv::PkvStore>| {
+        if let Err(e) = pkv.set(PKV_KEY, world.resource::<HighScores>()) {error!("{e}")};
+    });
+    let entries: Vec<Entity> = world.query_filtered::<Entity, With<RecordEntry>>().iter(world).collect();
+    for entry in entries {
+        world.entity_mut(entry).despawn_recursive();
+    }
+}
+
+fn spawn_leaderboard(
+    mut commands: Commands,
+) {
+    commands.add(eml! {
+        <div c:menu>
+            <label bind:value=from!(HighScores:best|fmt.c("Best: {c}"))/>
+            <label c:leaderboard bind:value=from!(HighScores:table|fmt.c("{c}"))/>
+            <div c:even>
+                <button on:press=run!(|c| {
+                    c.commands().add(|world: &mut World| {
+                        world.resource_mut::<NextState<GameState>>().set(GameState::OrbitSelect);
+                    })
+                })><label c:content value="Play Orbit"/></button>
+                <button on:press=run!(|c| {
+                    c.commands().add(|world: &mut World| {
+                        world.resource_mut::<NextState<GameState>>().set(GameState::MainMenu);
+                    })
+                })><label c:content value="Back"/></button>
+            </div>
+        </div>
+    });
+}
+
+fn spawn_record_entry(
+    mut commands: Commands,
+    record: Option<Res<PendingRecord>>,
+) {
+    let Some(record) = record else {return;};
+    let (score, name) = (record.score, record.name.clone());
+    commands.add(eml! {
+        <div c:record with:RecordEntry>
+            <label value={format!("New Record: {score}")}/>
+            <textinput value={name} bind:value=to!(PendingRecord:name)/>
+            <button on:press=run!(|c| {
+                c.commands().add(save_record)
+            })><label c:content value="Save"/></button>
+        </div>
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(name: &str, score: u16) -> HighScore {
+        HighScore { name: name.to_string(), score, date: 0, controls: OrbitControls::Classic }
+    }
+
+    fn full_table() -> HighScores {
+        let mut scores = HighScores::default();
+        for score in 1..=TABLE_SIZE as u16 {
+            scores.insert(run("Full", score * 10));
+        }
+        scores
+    }
+
+    #[test]
+    fn any_score_makes_a_table_with_room() {
+        let scores = HighScores::default();
+        assert!(scores.qualifies(1));
+        assert!(!scores.qualifies(0));
+    }
+
+    #[test]
+    fn full_table_needs_a_better_score_than_the_lowest() {
+        let scores = full_table();
+        assert!(!scores.qualifies(10));
+        assert!(scores.qualifies(11));
+    }
+
+    #[test]
+    fn insert_keeps_the_highest_first_and_truncates() {
+        let mut scores = full_table();
+        scores.insert(run("New", 55));
+        assert_eq!(scores.entries.len(), TABLE_SIZE);
+        assert!(scores.entries.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert_eq!(scores.entries.last().map(|entry| entry.score), Some(20));
+        assert_eq!(scores.best, 100);
+        assert_eq!(scores.last_name, "New");
+    }
+
+    #[test]
+    fn older_run_keeps_its_place_on_a_tie() {
+        let mut scores = HighScores::default();
+        scores.insert(run("First", 30));
+        scores.insert(run("Second", 30));
+        scores.insert(run("Third", 40));
+        let names: Vec<&str> = scores.entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["Third", "First", "Second"]);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_709_164_800), "2024-02-29");
+        // the last second of a leap year
+        assert_eq!(date(1_735_689_599), "2024-12-31");
+    }
+}
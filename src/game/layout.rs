@@ -0,0 +1,265 @@
+use bevy::{prelude::*, asset::{AssetLoader, LoadContext, LoadedAsset}, reflect::{TypePath, TypeUuid}, utils::BoxedFuture};
+use bevy_rapier2d::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::{GameState, Paused, Player};
+
+use super::*;
+use super::normal::{spawn_goal, spawn_paddle, spawn_wall};
+use super::rules::{MatchClock, MatchRules};
+
+const ARENA_DIR: &str = "arenas";
+
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_asset::<ArenaLayout>()
+        .init_asset_loader::<ArenaLayoutLoader>()
+        .init_resource::<ArenaLayouts>()
+        .add_systems(Startup, load_layouts)
+        .add_systems(Update, follow_layout_changes)
+        .add_systems(FixedUpdate, move_bumpers
+            .before(PhysicsSet::SyncBackend)
+            .run_if(in_state(GameState::PlayingNormal))
+            .run_if(in_state(Paused::Running)))
+        .add_systems(Update, rebuild_scene
+            .run_if(in_state(GameState::PlayingNormal))
+            .run_if(resource_changed::<SelectedLayout>())
+            .run_if(not(resource_exists::<replay::ReplayPlayback>()))
+            .run_if(not(resource_exists::<net::NetSession>())));
+    }
+}
+
+/// a normal mode field read from an `.arena.ron` file in `assets/arenas`.
+/// Positions and sizes are fractions of the [`Arena`], (0, 0) is the middle and (±0.5, ±0.5) the edges
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TypeUuid, TypePath)]
+#[uuid = "5b0f1c64-8f3e-4d1a-a2c7-9e4d3b6a7f10"]
+#[serde(default)]
+pub struct ArenaLayout {
+    pub name: String,
+    pub walls: Vec<Block>,
+    /// goals of seats that aren't playing are walls instead
+    pub goals: Vec<GoalZone>,
+    pub bumpers: Vec<Bumper>,
+    pub lanes: Vec<Lane>,
+    /// where balls are served from, the middle when empty
+    pub serves: Vec<Vec2>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Block {
+    pub pos: Vec2,
+    pub size: Vec2,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalZone {
+    pub player: Player,
+    pub pos: Vec2,
+    pub size: Vec2,
+}
+
+/// a round obstacle, `radius` is a fraction of the arena's height
+#[derive(Debug, Clone, Serialize, Deserialize, Component)]
+pub struct Bumper {
+    pub pos: Vec2,
+    pub radius: f32,
+    /// glides to here and back when set
+    #[serde(default)]
+    pub moves_to: Option<Vec2>,
+    /// seconds for a trip there and back
+    #[serde(default = "Bumper::default_period")]
+    pub period: f32,
+}
+
+impl Bumper {
+    fn default_period() -> f32 {
+        4.
+    }
+}
+
+/// where a seat's paddle sits and how far it can slide
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lane {
+    pub player: Player,
+    pub pos: Vec2,
+    pub size: Vec2,
+    #[serde(default)]
+    pub horizontal: bool,
+    /// furthest the paddle's centre gets from the middle of the field either way
+    pub travel: f32,
+}
+
+#[derive(Default)]
+struct ArenaLayoutLoader;
+
+impl AssetLoader for ArenaLayoutLoader {
+    fn load<'a>(&'a self, bytes: &'a [u8], load_context: &'a mut LoadContext) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let layout: ArenaLayout = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(layout));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["arena.ron"]
+    }
+}
+
+/// every arena found in `assets/arenas`, in the order the picker cycles through them
+#[derive(Resource, Default)]
+struct ArenaLayouts(Vec<Handle<ArenaLayout>>);
+
+/// the arena the next match is played in, `None` is the built in field for the mode
+#[derive(Resource)]
+pub struct SelectedLayout {
+    pub name: String,
+    pub layout: Option<ArenaLayout>,
+    /// the asset it came from, so edits to the file show up straight away
+    handle: Option<Handle<ArenaLayout>>,
+}
+
+impl Default for SelectedLayout {
+    fn default() -> Self {
+        SelectedLayout { name: "Classic".to_string(), layout: None, handle: None }
+    }
+}
+
+impl SelectedLayout {
+    /// a layout that didn't come from the arenas folder, like one saved in a replay
+    pub fn fixed(layout: Option<ArenaLayout>) -> SelectedLayout {
+        match layout {
+            Some(layout) => SelectedLayout { name: layout.name.clone(), layout: Some(layout), handle: None },
+            None => SelectedLayout::default(),
+        }
+    }
+}
+
+fn load_layouts(
+    asset_server: Res<AssetServer>,
+    mut layouts: ResMut<ArenaLayouts>,
+) {
+    match asset_server.load_folder(ARENA_DIR) {
+        Ok(handles) => layouts.0 = handles.into_iter().map(|handle| handle.typed()).collect(),
+        Err(e) => warn!("No arenas loaded from {ARENA_DIR}: {e}"),
+    }
+}
+
+fn follow_layout_changes(
+    mut events: EventReader<AssetEvent<ArenaLayout>>,
+    assets: Res<Assets<ArenaLayout>>,
+    mut selected: ResMut<SelectedLayout>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {continue;};
+        if selected.handle.as_ref() != Some(handle) {continue;}
+        let Some(layout) = assets.get(handle) else {continue;};
+        info!("Reloaded arena {}", layout.name);
+        selected.name = layout.name.clone();
+        selected.layout = Some(layout.clone());
+    }
+}
+
+/// the picker's button, goes through every loaded arena then back to the built in one
+pub fn next_layout(world: &mut World) {
+    let handles = world.resource::<ArenaLayouts>().0.clone();
+    let assets = world.resource::<Assets<ArenaLayout>>();
+    let current = world.resource::<SelectedLayout>().handle.as_ref()
+        .and_then(|current| handles.iter().position(|handle| handle == current));
+    let start = current.map_or(0, |index| index + 1);
+    let next = handles.iter().skip(start)
+        .find_map(|handle| assets.get(handle).map(|layout| (handle.clone(), layout.clone())));
+    *world.resource_mut::<SelectedLayout>() = match next {
+        Some((handle, layout)) => SelectedLayout { name: layout.name.clone(), layout: Some(layout), handle: Some(handle) },
+        None => SelectedLayout::default(),
+    };
+}
+
+/// part of the field built from an [`ArenaLayout`], torn down and rebuilt when the file changes
+#[derive(Component)]
+struct LayoutItem;
+
+pub fn spawn_layout(commands: &mut Commands, layout: &ArenaLayout, size: Vec2, rules: &MatchRules) {
+    let players = rules.mode.players();
+    for wall in &layout.walls {
+        let wall = spawn_wall(commands, "Wall", wall.pos * size, wall.size * size / 2.);
+        commands.entity(wall).insert(LayoutItem);
+    }
+    for goal in &layout.goals {
+        let (pos, half_size) = (goal.pos * size, goal.size * size / 2.);
+        let entity = if players.contains(&goal.player) {
+            spawn_goal(commands, goal.player, pos, half_size)
+        } else {
+            spawn_wall(commands, "Closed Goal", pos, half_size)
+        };
+        commands.entity(entity).insert(LayoutItem);
+    }
+    for lane in &layout.lanes {
+        if !players.contains(&lane.player) {continue;}
+        let travel = lane.travel * if lane.horizontal {size.x} else {size.y};
+        let paddle = spawn_paddle(commands, lane.player, lane.pos * size, lane.size * size, lane.horizontal, travel);
+        commands.entity(paddle).insert(LayoutItem);
+    }
+    for bumper in &layout.bumpers {
+        let radius = bumper.radius * size.y;
+        commands.spawn((SpriteBundle {
+            transform: Transform::from_translation((bumper.pos * size).extend(0.)),
+            sprite: Sprite {
+                color: Color::GRAY,
+                custom_size: Some(Vec2::splat(radius * 2.)),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Name::new("Bumper"),
+        GameItem,
+        LayoutItem,
+        if bumper.moves_to.is_some() {RigidBody::KinematicPositionBased} else {RigidBody::Fixed},
+        Collider::ball(radius),
+        Restitution{coefficient: 1.0, ..Default::default()},
+        Friction{coefficient: 0., ..Default::default()},
+        bumper.clone(),
+        ));
+    }
+}
+
+/// bumpers follow the match clock so replays and rollback put them in the same place
+pub fn move_bumpers(
+    mut bumpers: Query<(&mut Transform, &Bumper)>,
+    clock: Res<MatchClock>,
+    arena: Res<Arena>,
+) {
+    for (mut transform, bumper) in &mut bumpers {
+        let Some(to) = bumper.moves_to else {continue;};
+        let phase = clock.elapsed / bumper.period.max(0.01) * std::f32::consts::TAU;
+        let t = (1. - phase.cos()) / 2.;
+        let pos = bumper.pos.lerp(to, t) * arena.size;
+        transform.translation = pos.extend(transform.translation.z);
+    }
+}
+
+/// a random serve point from the layout, if it has any
+pub fn serve_point(layout: &SelectedLayout, size: Vec2, rng: &mut impl rand::Rng) -> Option<Vec2> {
+    let serves = &layout.layout.as_ref()?.serves;
+    if serves.is_empty() {return None;}
+    Some(serves[rng.gen_range(0..serves.len())] * size)
+}
+
+/// swaps the field for the edited one, the score and balls in play carry on
+fn rebuild_scene(
+    mut commands: Commands,
+    items: Query<Entity, With<LayoutItem>>,
+    selected: Res<SelectedLayout>,
+    arena: Res<Arena>,
+    rules: Res<MatchRules>,
+) {
+    if items.is_empty() {return;}
+    let Some(layout) = &selected.layout else {return;};
+    for item in &items {
+        commands.entity(item).despawn_recursive();
+    }
+    spawn_layout(&mut commands, layout, arena.size, &rules);
+}
@@ -0,0 +1,709 @@
+use std::{collections::VecDeque, net::{SocketAddr, UdpSocket}, time::{Duration, Instant}};
+
+use belly::prelude::*;
+use bevy::{prelude::*, ecs::system::CommandQueue};
+use bevy_rapier2d::{prelude::*, plugin::systems::sync_removals, rapier::{
+    dynamics::{CCDSolver, IslandManager, RigidBodyHandle, RigidBodySet},
+    geometry::{BroadPhase, ColliderSet, NarrowPhase},
+    pipeline::QueryPipeline,
+}};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::{GameState, Paused, Player, ai::{AiBrains, AiUpdate}};
+
+use super::*;
+use super::normal::{move_paddle, spawn_ball_in, PaddleInputs, ReadInputs, Trail};
+use super::rules::{MatchClock, MatchRules, NormalMode};
+
+pub const PORT: u16 = 7878;
+/// how many ticks we'll run ahead of the last input we heard from the other player
+const MAX_PREDICTION: u32 = 15;
+/// most inputs sent in one packet, older ones the other side hasn't acknowledged are resent
+const MAX_RESEND: u32 = 30;
+const HELLO_INTERVAL: f32 = 0.5;
+const LATENCIES: [u32; 4] = [0, 50, 100, 250];
+const LOSSES: [u32; 4] = [0, 5, 10, 25];
+
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<NetSettings>()
+        .add_systems(OnEnter(GameState::NetLobby), spawn_lobby_menu)
+        .add_systems(OnExit(GameState::NetLobby), (crate::menu::close_menu, |mut commands: Commands| commands.remove_resource::<NetLobby>()))
+        .add_systems(Update, lobby.run_if(in_state(GameState::NetLobby)).run_if(resource_exists::<NetLobby>()))
+        .add_systems(PreUpdate, net_update.run_if(in_state(GameState::PlayingNormal)).run_if(resource_exists::<NetSession>()))
+        .add_systems(FixedUpdate, save_snapshot.before(AiUpdate)
+            .run_if(in_state(GameState::PlayingNormal)).run_if(in_state(Paused::Running)).run_if(resource_exists::<NetSession>()))
+        .add_systems(FixedUpdate, net_inputs.after(ReadInputs).before(move_paddle)
+            .run_if(in_state(GameState::PlayingNormal)).run_if(in_state(Paused::Running)).run_if(resource_exists::<NetSession>()))
+        .add_systems(OnExit(GameState::PlayingNormal), end_session.run_if(resource_exists::<NetSession>()));
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Packet {
+    Hello { name: String },
+    Welcome { seed: u64, rules: MatchRules, arena: Vec2, layout: Option<ArenaLayout>, name: String },
+    /// inputs from tick `start` on, `ack` is how many of the receiver's inputs have arrived so far
+    Inputs { start: u32, inputs: Vec<f32>, ack: u32 },
+    Spectate { name: String },
+    /// `names` of the seats in play, in seat order
+    Watching { names: Vec<String>, arena: Vec2 },
+    State(WorldState),
+    /// milliseconds since the sender started watching, echoed straight back as a `Pong`
+    Ping(u64),
+    Pong(u64),
+    Bye,
+}
+
+/// what spectators see of a match
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WorldState {
+    pub tick: u32,
+    /// position and size of every paddle
+    pub paddles: Vec<(Player, Vec2, Vec2)>,
+    /// position and velocity of every ball
+    pub balls: Vec<(BallId, Vec2, Vec2)>,
+    pub score: Score,
+}
+
+/// what the lobby menu edits
+#[derive(Resource)]
+pub struct NetSettings {
+    pub name: String,
+    pub address: String,
+    /// extra delay added to every packet we send, in milliseconds
+    pub latency: u32,
+    /// percentage of packets we drop on purpose
+    pub loss: u32,
+    /// let spectators watch any normal match played on this machine
+    pub spectators: bool,
+    pub status: String,
+}
+
+impl Default for NetSettings {
+    fn default() -> Self {
+        NetSettings {
+            name: "Player".to_string(),
+            address: format!("127.0.0.1:{PORT}"),
+            latency: 0,
+            loss: 0,
+            spectators: false,
+            status: String::new(),
+        }
+    }
+}
+
+/// a udp socket that can pretend to be a bad network
+pub struct NetLink {
+    socket: UdpSocket,
+    /// where [`NetLink::send`] goes, packets from anyone else are ignored while this is set
+    pub peer: Option<SocketAddr>,
+    latency: Duration,
+    loss: f64,
+    outbox: VecDeque<(Instant, SocketAddr, Vec<u8>)>,
+}
+
+impl NetLink {
+    pub fn bind(address: SocketAddr, settings: &NetSettings) -> std::io::Result<NetLink> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetLink {
+            socket,
+            peer: None,
+            latency: Duration::from_millis(settings.latency as u64),
+            loss: settings.loss as f64 / 100.,
+            outbox: VecDeque::new(),
+        })
+    }
+
+    pub fn send(&mut self, packet: &Packet) {
+        let Some(peer) = self.peer else {return;};
+        self.send_to(peer, packet);
+    }
+
+    pub fn send_to(&mut self, to: SocketAddr, packet: &Packet) {
+        if self.loss > 0. && rand::thread_rng().gen_bool(self.loss) {return;}
+        let bytes = serde_json::to_vec(packet).expect("packets are always serializable");
+        self.outbox.push_back((Instant::now() + self.latency, to, bytes));
+        self.flush();
+    }
+
+    /// sends everything whose simulated latency has passed
+    pub fn flush(&mut self) {
+        while self.outbox.front().is_some_and(|(at, _, _)| *at <= Instant::now()) {
+            let (_, to, bytes) = self.outbox.pop_front().unwrap();
+            if let Err(e) = self.socket.send_to(&bytes, to) {
+                warn!("Failed to send packet: {e}");
+            }
+        }
+    }
+
+    pub fn receive(&mut self) -> Vec<(SocketAddr, Packet)> {
+        let mut packets = Vec::new();
+        let mut buffer = [0; 4096];
+        while let Ok((len, from)) = self.socket.recv_from(&mut buffer) {
+            if self.peer.is_some_and(|peer| peer != from) {continue;}
+            match serde_json::from_slice(&buffer[..len]) {
+                Ok(packet) => packets.push((from, packet)),
+                Err(e) => warn!("Bad packet from {from}: {e}"),
+            }
+        }
+        packets
+    }
+}
+
+/// waiting for the other player before a match starts
+#[derive(Resource)]
+struct NetLobby {
+    link: NetLink,
+    host: bool,
+    since_hello: f32,
+}
+
+fn host(world: &mut World) {
+    let mut settings = world.resource_mut::<NetSettings>();
+    match NetLink::bind(SocketAddr::from(([0, 0, 0, 0], PORT)), &settings) {
+        Ok(link) => {
+            settings.status = format!("Waiting for a player on port {PORT}");
+            world.insert_resource(NetLobby { link, host: true, since_hello: 0. });
+        },
+        Err(e) => settings.status = format!("Can't host: {e}"),
+    }
+}
+
+fn join(world: &mut World) {
+    let mut settings = world.resource_mut::<NetSettings>();
+    let peer = match settings.address.parse::<SocketAddr>() {
+        Ok(peer) => peer,
+        Err(_) => {
+            settings.status = format!("{} isn't an address", settings.address);
+            return;
+        }
+    };
+    match NetLink::bind(SocketAddr::from(([0, 0, 0, 0], 0)), &settings) {
+        Ok(mut link) => {
+            link.peer = Some(peer);
+            settings.status = format!("Joining {peer}");
+            world.insert_resource(NetLobby { link, host: false, since_hello: HELLO_INTERVAL });
+        },
+        Err(e) => settings.status = format!("Can't join: {e}"),
+    }
+}
+
+fn lobby(
+    mut commands: Commands,
+    mut lobby: ResMut<NetLobby>,
+    rules: Res<MatchRules>,
+    arena: Res<Arena>,
+    layout: Res<SelectedLayout>,
+    settings: Res<NetSettings>,
+    time: Res<Time>,
+) {
+    lobby.link.flush();
+    if !lobby.host {
+        lobby.since_hello += time.delta_seconds();
+        if lobby.since_hello >= HELLO_INTERVAL {
+            lobby.since_hello = 0.;
+            lobby.link.send(&Packet::Hello { name: settings.name.clone() });
+        }
+    }
+    for (from, packet) in lobby.link.receive() {
+        match (lobby.host, packet) {
+            (true, Packet::Hello { name }) => {
+                lobby.link.peer = Some(from);
+                let seed = rand::random();
+                // only two seats go over the wire and rollback doesn't snapshot power-ups
+                let mut rules = MatchRules { mode: NormalMode::Versus, ..rules.clone() };
+                rules.power_ups.enabled = false;
+                let welcome = Packet::Welcome { seed, rules: rules.clone(), arena: arena.size, layout: layout.layout.clone(), name: settings.name.clone() };
+                lobby.link.send(&welcome);
+                let mut names = PlayerNames::default();
+                names.0[0] = settings.name.clone();
+                names.0[1] = name;
+                commands.insert_resource(names);
+                start_match(&mut commands, seed, rules, arena.size, Player::PlayerOne);
+                commands.add(move |world: &mut World| {
+                    let NetLobby { link, .. } = world.remove_resource::<NetLobby>().unwrap();
+                    let mut session = NetSession::new(link, Player::PlayerOne);
+                    session.welcome = Some(welcome);
+                    world.insert_resource(session);
+                });
+                return;
+            },
+            (false, Packet::Welcome { seed, rules, arena, layout, name }) => {
+                let mut names = PlayerNames::default();
+                names.0[0] = name;
+                names.0[1] = settings.name.clone();
+                commands.insert_resource(names);
+                commands.insert_resource(SelectedLayout::fixed(layout));
+                start_match(&mut commands, seed, rules, arena, Player::PlayerTwo);
+                commands.add(|world: &mut World| {
+                    let NetLobby { link, .. } = world.remove_resource::<NetLobby>().unwrap();
+                    world.insert_resource(NetSession::new(link, Player::PlayerTwo));
+                });
+                return;
+            },
+            _ => {},
+        }
+    }
+}
+
+fn start_match(commands: &mut Commands, seed: u64, rules: MatchRules, arena: Vec2, local: Player) {
+    info!("Starting online match as {:?}", local);
+    commands.insert_resource(MatchSeed(Some(seed)));
+    commands.insert_resource(rules);
+    commands.insert_resource(Arena { size: arena });
+    commands.insert_resource(AiBrains::default());
+    commands.add(|world: &mut World| {
+        world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+    });
+}
+
+/// an online match in progress, the simulation is rolled back and replayed
+/// whenever an input from the other player turns out to differ from our guess
+#[derive(Resource)]
+pub struct NetSession {
+    link: NetLink,
+    local: Player,
+    remote: Player,
+    /// the tick about to be simulated
+    tick: u32,
+    local_inputs: Vec<f32>,
+    remote_inputs: Vec<Option<f32>>,
+    /// the remote input each tick was last simulated with
+    used_remote: Vec<f32>,
+    /// how many remote inputs have arrived without gaps
+    confirmed: u32,
+    /// how many of our inputs the other side has confirmed
+    acked: u32,
+    rollback_to: Option<u32>,
+    snapshots: VecDeque<Snapshot>,
+    resimulating: bool,
+    /// kept by the host to resend if the joiner never got it
+    welcome: Option<Packet>,
+}
+
+impl NetSession {
+    fn new(link: NetLink, local: Player) -> NetSession {
+        NetSession {
+            link,
+            local,
+            remote: if local == Player::PlayerOne {Player::PlayerTwo} else {Player::PlayerOne},
+            tick: 0,
+            local_inputs: Vec::new(),
+            remote_inputs: Vec::new(),
+            used_remote: Vec::new(),
+            confirmed: 0,
+            acked: 0,
+            rollback_to: None,
+            snapshots: VecDeque::new(),
+            resimulating: false,
+            welcome: None,
+        }
+    }
+
+    /// the remote input for `tick`, or the latest one we have before it
+    fn remote_input(&self, tick: u32) -> f32 {
+        let end = (tick as usize + 1).min(self.remote_inputs.len());
+        self.remote_inputs[..end].iter().rev().flatten().next().copied().unwrap_or(0.)
+    }
+
+    fn receive_inputs(&mut self, start: u32, inputs: Vec<f32>) {
+        for (i, input) in inputs.into_iter().enumerate() {
+            let tick = start as usize + i;
+            if self.remote_inputs.len() <= tick {
+                self.remote_inputs.resize(tick + 1, None);
+            }
+            if self.remote_inputs[tick].is_some() {continue;}
+            self.remote_inputs[tick] = Some(input);
+            if self.used_remote.get(tick).is_some_and(|used| *used != input) {
+                let tick = tick as u32;
+                self.rollback_to = Some(self.rollback_to.map_or(tick, |to| to.min(tick)));
+            }
+        }
+        while self.remote_inputs.get(self.confirmed as usize).is_some_and(Option::is_some) {
+            self.confirmed += 1;
+        }
+    }
+
+    fn send_inputs(&mut self) {
+        let start = self.acked.max((self.local_inputs.len() as u32).saturating_sub(MAX_RESEND));
+        let packet = Packet::Inputs {
+            start,
+            inputs: self.local_inputs[start as usize..].to_vec(),
+            ack: self.confirmed,
+        };
+        self.link.send(&packet);
+    }
+}
+
+/// everything a tick can change, enough to rewind to the start of `tick`
+#[derive(Clone)]
+struct Snapshot {
+    tick: u32,
+    paddles: Vec<(Player, Transform)>,
+    balls: Vec<SavedBall>,
+    ball_ids: BallIds,
+    score: Score,
+    rally: (u32, u32),
+    clock: f32,
+    rng: GameRng,
+    physics: Physics,
+}
+
+#[derive(Clone)]
+struct SavedBall {
+    id: BallId,
+    transform: Transform,
+    velocity: Velocity,
+    color: Color,
+    trail: bool,
+    /// its body in [`Snapshot::physics`], none if it was served too late in the tick for rapier to have picked it up
+    body: Option<RigidBodyHandle>,
+}
+
+/// rapier's copy of the world, so bodies, contacts and ccd carry on from the snapshot rather than the predicted future
+#[derive(Clone)]
+struct Physics {
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+}
+
+impl Physics {
+    fn save(context: &RapierContext) -> Physics {
+        Physics {
+            islands: context.islands.clone(),
+            broad_phase: context.broad_phase.clone(),
+            narrow_phase: context.narrow_phase.clone(),
+            bodies: context.bodies.clone(),
+            colliders: context.colliders.clone(),
+            ccd_solver: context.ccd_solver.clone(),
+            query_pipeline: context.query_pipeline.clone(),
+        }
+    }
+
+    fn restore(&self, context: &mut RapierContext) {
+        context.islands = self.islands.clone();
+        context.broad_phase = self.broad_phase.clone();
+        context.narrow_phase = self.narrow_phase.clone();
+        context.bodies = self.bodies.clone();
+        context.colliders = self.colliders.clone();
+        context.ccd_solver = self.ccd_solver.clone();
+        context.query_pipeline = self.query_pipeline.clone();
+    }
+}
+
+impl Snapshot {
+    fn restore(&self, world: &mut World) {
+        let mut paddles = world.query_filtered::<(&Player, &mut Transform), With<Paddle>>();
+        for (player, mut transform) in paddles.iter_mut(world) {
+            if let Some((_, saved)) = self.paddles.iter().find(|(p, _)| p == player) {
+                *transform = *saved;
+            }
+        }
+        let balls: Vec<(Entity, BallId)> = world.query_filtered::<(Entity, &BallId), With<Ball>>().iter(world)
+            .map(|(ball, id)| (ball, *id))
+            .collect();
+        for (ball, id) in &balls {
+            match self.balls.iter().find(|saved| saved.id == *id) {
+                Some(saved) => {world.entity_mut(*ball).insert((saved.transform, saved.velocity));},
+                None => {world.despawn(*ball);},
+            }
+        }
+        // rapier has to let go of the despawned balls while their handles still mean the same bodies
+        let mut removals = Schedule::new();
+        removals.add_systems(sync_removals);
+        removals.run(world);
+
+        let missing: Vec<&SavedBall> = self.balls.iter().filter(|saved| !balls.iter().any(|(_, alive)| *alive == saved.id)).collect();
+        {
+            let mut context = world.resource_mut::<RapierContext>();
+            let context = &mut *context;
+            self.physics.restore(context);
+            // a ball that has to come back gets a new entity and with it a new body, so drop the saved one
+            for body in missing.iter().filter_map(|saved| saved.body) {
+                context.bodies.remove(body, &mut context.islands, &mut context.colliders, &mut context.impulse_joints, &mut context.multibody_joints, true);
+            }
+        }
+        if !missing.is_empty() {
+            let ball_size = world.resource::<Arena>().size.y * 0.05;
+            let asset_server = world.get_resource::<AssetServer>().cloned();
+            let mut queue = CommandQueue::default();
+            let mut commands = Commands::new(&mut queue, world);
+            for saved in missing {
+                let ball = spawn_ball_in(&mut commands, saved.id, saved.velocity.linvel, ball_size, saved.color, asset_server.as_ref());
+                commands.entity(ball).insert(saved.transform);
+                if saved.trail {
+                    commands.entity(ball).insert(Trail);
+                }
+            }
+            queue.apply(world);
+        }
+        world.insert_resource(self.ball_ids);
+        world.insert_resource(self.score);
+        let mut rally = world.resource_mut::<Rally>();
+        (rally.hits, rally.total_hits) = self.rally;
+        world.resource_mut::<MatchClock>().elapsed = self.clock;
+        world.insert_resource(self.rng.clone());
+    }
+}
+
+fn save_snapshot(
+    paddles: Query<(&Player, &Transform), With<Paddle>>,
+    balls: Query<(&BallId, &Transform, &Velocity, &Sprite, Option<&Trail>, Option<&RapierRigidBodyHandle>), With<Ball>>,
+    ball_ids: Res<BallIds>,
+    score: Res<Score>,
+    rally: Res<Rally>,
+    clock: Res<MatchClock>,
+    rng: Res<GameRng>,
+    context: Res<RapierContext>,
+    mut session: ResMut<NetSession>,
+) {
+    let snapshot = Snapshot {
+        tick: session.tick,
+        paddles: paddles.iter().map(|(player, transform)| (*player, *transform)).collect(),
+        balls: balls.iter().map(|(id, transform, velocity, sprite, trail, body)| SavedBall {
+            id: *id,
+            transform: *transform,
+            velocity: *velocity,
+            color: sprite.color,
+            trail: trail.is_some(),
+            body: body.map(|body| body.0),
+        }).collect(),
+        ball_ids: *ball_ids,
+        score: *score,
+        rally: (rally.hits, rally.total_hits),
+        clock: clock.elapsed,
+        rng: rng.clone(),
+        physics: Physics::save(&context),
+    };
+    session.snapshots.push_back(snapshot);
+}
+
+fn net_inputs(
+    mut session: ResMut<NetSession>,
+    mut inputs: ResMut<PaddleInputs>,
+) {
+    let tick = session.tick;
+    if !session.resimulating {
+        let input = inputs.0.get(&session.local).copied().unwrap_or(0.);
+        session.local_inputs.push(input);
+        session.send_inputs();
+    }
+    let local = session.local_inputs[tick as usize];
+    let remote = session.remote_input(tick);
+    if session.used_remote.len() > tick as usize {
+        session.used_remote[tick as usize] = remote;
+    } else {
+        session.used_remote.push(remote);
+    }
+    inputs.0.clear();
+    inputs.0.insert(session.local, local);
+    inputs.0.insert(session.remote, remote);
+    session.tick += 1;
+}
+
+fn net_update(world: &mut World) {
+    let mut session = world.resource_mut::<NetSession>();
+    session.link.flush();
+    let mut left = false;
+    for (_, packet) in session.link.receive() {
+        match packet {
+            Packet::Hello { .. } => if let Some(welcome) = session.welcome.take() {
+                session.link.send(&welcome);
+                session.welcome = Some(welcome);
+            },
+            Packet::Inputs { start, inputs, ack } => {
+                session.acked = session.acked.max(ack);
+                session.receive_inputs(start, inputs);
+            },
+            Packet::Bye => left = true,
+            _ => {},
+        }
+    }
+    if left {
+        world.resource_mut::<NetSettings>().status = "The other player left".to_string();
+        world.resource_mut::<NextState<GameState>>().set(GameState::NetLobby);
+        return;
+    }
+
+    if let Some(to) = session.rollback_to.take() {
+        rollback(world, to);
+    }
+
+    let mut session = world.resource_mut::<NetSession>();
+    let confirmed = session.confirmed;
+    session.snapshots.retain(|snapshot| snapshot.tick >= confirmed);
+    // too far ahead of the other player, wait for them to catch up
+    let stalled = session.tick >= confirmed + MAX_PREDICTION;
+    let mut time = world.resource_mut::<Time>();
+    if stalled != time.is_paused() {
+        if stalled {time.pause()} else {time.unpause()}
+    }
+}
+
+fn rollback(world: &mut World, to: u32) {
+    let mut session = world.resource_mut::<NetSession>();
+    let Some(index) = session.snapshots.iter().position(|snapshot| snapshot.tick == to) else {
+        warn!("No snapshot for tick {to}, can't roll back");
+        return;
+    };
+    let snapshot = session.snapshots[index].clone();
+    // the ticks being replayed save their snapshots again
+    session.snapshots.truncate(index);
+    let ticks = session.tick - to;
+    session.tick = to;
+    session.resimulating = true;
+    snapshot.restore(world);
+    for _ in 0..ticks {
+        world.run_schedule(FixedUpdate);
+    }
+    world.resource_mut::<NetSession>().resimulating = false;
+}
+
+fn end_session(
+    mut commands: Commands,
+    mut session: ResMut<NetSession>,
+    mut time: ResMut<Time>,
+) {
+    session.link.latency = Duration::ZERO;
+    session.link.loss = 0.;
+    session.link.send(&Packet::Bye);
+    commands.remove_resource::<NetSession>();
+    commands.insert_resource(MatchSeed(None));
+    time.unpause();
+}
+
+fn spawn_lobby_menu(
+    mut commands: Commands,
+    settings: Res<NetSettings>,
+) {
+    let address = settings.address.clone();
+    let name = settings.name.clone();
+    commands.add(eml! {
+        <div c:menu>
+            <textinput value={name} bind:value=to!(NetSettings:name)/>
+            <textinput value={address} bind:value=to!(NetSettings:address)/>
+            <div c:even>
+                <button on:press=run!(|c| {
+                    c.commands().add(host)
+                })><label c:content value="Host"/></button>
+                <button on:press=run!(|c| {
+                    c.commands().add(join)
+                })><label c:content value="Join"/></button>
+                <button on:press=run!(|c| {
+                    c.commands().add(super::spectate::spectate)
+                })><label c:content value="Spectate"/></button>
+            </div>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut settings = world.resource_mut::<NetSettings>();
+                    settings.spectators = !settings.spectators;
+                })
+            })><label bind:value=from!(NetSettings:spectators|fmt.c("Allow Spectators: {c}"))/></button>
+            <div c:even>
+                <button on:press=run!(|c| {
+                    c.commands().add(|world: &mut World| {
+                        let mut settings = world.resource_mut::<NetSettings>();
+                        let next = LATENCIES.iter().position(|l| *l == settings.latency).map_or(0, |i| (i + 1) % LATENCIES.len());
+                        settings.latency = LATENCIES[next];
+                    })
+                })><label bind:value=from!(NetSettings:latency|fmt.c("Fake Latency: {c}ms"))/></button>
+                <button on:press=run!(|c| {
+                    c.commands().add(|world: &mut World| {
+                        let mut settings = world.resource_mut::<NetSettings>();
+                        let next = LOSSES.iter().position(|l| *l == settings.loss).map_or(0, |i| (i + 1) % LOSSES.len());
+                        settings.loss = LOSSES[next];
+                    })
+                })><label bind:value=from!(NetSettings:loss|fmt.c("Fake Loss: {c}%"))/></button>
+            </div>
+            <label bind:value=from!(NetSettings:status|fmt.c("{c}"))/>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::MainMenu);
+                })
+            })><label c:content value="Back"/></button>
+        </div>
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::headless_app;
+
+    /// when the other player's stick goes from full up to full down
+    const TURN: u32 = 185;
+
+    fn remote(tick: u32) -> f32 {
+        if tick < TURN {1.} else {-1.}
+    }
+
+    /// `ticks` of an online match with the other player's inputs fed straight into the session,
+    /// the ones for `late` held back and then arriving together on its last tick
+    fn online_match(late: std::ops::Range<u32>, ticks: u32) -> App {
+        let mut app = headless_app(Vec2::new(1280., 720.));
+        app.add_plugins(NetPlugin);
+        let link = NetLink::bind(SocketAddr::from(([127, 0, 0, 1], 0)), &NetSettings::default()).expect("a free local port");
+        app.insert_resource(NetSession::new(link, Player::PlayerOne));
+        app.world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+        for _ in 0..ticks {
+            let mut session = app.world.resource_mut::<NetSession>();
+            let tick = session.tick;
+            if tick == late.end {
+                session.receive_inputs(late.start, (late.start..=tick).map(remote).collect());
+            } else if !late.contains(&tick) {
+                session.receive_inputs(tick, vec![remote(tick)]);
+            }
+            app.update();
+        }
+        app
+    }
+
+    /// paddle and ball positions plus the ball velocities, in seat and ball id order
+    fn bodies(app: &mut App) -> Vec<Vec3> {
+        let mut paddles: Vec<(Player, Vec3)> = app.world.query_filtered::<(&Player, &Transform), With<Paddle>>().iter(&app.world)
+            .map(|(player, transform)| (*player, transform.translation))
+            .collect();
+        paddles.sort_by_key(|(player, _)| *player);
+        let mut balls: Vec<(u32, Vec3, Vec2)> = app.world.query_filtered::<(&BallId, &Transform, &Velocity), With<Ball>>().iter(&app.world)
+            .map(|(id, transform, velocity)| (id.0, transform.translation, velocity.linvel))
+            .collect();
+        balls.sort_by_key(|(id, ..)| *id);
+        paddles.into_iter().map(|(_, at)| at)
+            .chain(balls.into_iter().flat_map(|(_, at, velocity)| [at, velocity.extend(0.)]))
+            .collect()
+    }
+
+    fn assert_same_match(on_time: &mut App, rolled_back: &mut App) {
+        assert_eq!(on_time.world.resource::<NetSession>().tick, rolled_back.world.resource::<NetSession>().tick);
+        let (expected, actual) = (bodies(on_time), bodies(rolled_back));
+        assert_eq!(expected.len(), actual.len());
+        for (expected, actual) in expected.iter().zip(&actual) {
+            assert!(expected.distance(*actual) < 1e-3, "expected {expected} but rolled back to {actual}");
+        }
+        let (expected, actual) = (on_time.world.resource::<Score>(), rolled_back.world.resource::<Score>());
+        assert_eq!((expected.0, expected.1), (actual.0, actual.1));
+    }
+
+    #[test]
+    fn late_input_rolls_back_to_the_same_match() {
+        let late = 180..192;
+        for ticks in [late.end + 1, 300] {
+            let mut on_time = online_match(0..0, ticks);
+            let mut rolled_back = online_match(late.clone(), ticks);
+            let session = rolled_back.world.resource::<NetSession>();
+            assert!((TURN..late.end).all(|tick| session.used_remote[tick as usize] == remote(tick)), "the late inputs were never replayed");
+            assert_same_match(&mut on_time, &mut rolled_back);
+        }
+    }
+}
@@ -1,11 +1,15 @@
 use belly::prelude::*;
-use bevy::{prelude::*, window::PrimaryWindow, sprite::Anchor};
+use bevy::{prelude::*, sprite::Anchor};
 use bevy_rapier2d::prelude::*;
 use rand::Rng;
+use std::collections::HashMap;
 
-use crate::{GameState, Player, PlayerKeyBinds, ai::{Opponent, AiBrain, PongAi}, KeyBindings};
+use crate::{GameState, Paused, Player, bindings::{PlayerInput, ControlScheme}, ai::{AiBrains, AiSettings, AiUpdate, PongAi, SeatKind, SeatSelection}};
 
 use super::*;
+use super::rules::{tick_clock, BallRules, MatchRules, MatchClock, NormalMode};
+use super::powerup::{BallSpeedScale, PaddleEffects, PowerUpKind, PowerUps};
+use super::layout::{serve_point, spawn_layout};
 
 pub struct NormalPlugin;
 
@@ -13,12 +17,18 @@ impl Plugin for NormalPlugin {
     fn build(&self, app: &mut App) {
         app
         .add_event::<GameEvent>()
-        .add_systems(OnEnter(GameState::PlayingNormal), (spawn_game_scene, spawn_score))
+        .add_systems(OnEnter(GameState::PlayingNormal), spawn_game_scene)
         .add_systems(OnExit(GameState::PlayingNormal), clean_up_game)
-        .add_systems(Update, move_paddle.in_set(Playing))
-        .add_systems(Update, spawn_ball.in_set(Playing))
-        .add_systems(Update, score_point.in_set(Playing))
-        .configure_set(Update, Playing.run_if(in_state(GameState::PlayingNormal)))
+        .init_resource::<PaddleInputs>()
+        .add_systems(FixedUpdate, read_paddle_inputs.in_set(ReadInputs).in_set(Playing).after(AiUpdate))
+        .add_systems(FixedUpdate, move_paddle.in_set(Playing).after(ReadInputs).before(PhysicsSet::SyncBackend))
+        .add_systems(FixedUpdate, (score_point, count_rally, multiball_timer, apply_english, limit_ball_speed, spawn_ball).chain().in_set(Playing).after(PhysicsSet::Writeback).after(tick_clock))
+        .configure_set(FixedUpdate, Playing.run_if(in_state(GameState::PlayingNormal)).run_if(in_state(Paused::Running)));
+
+        if app.world.contains_resource::<Headless>() {return;}
+        app
+        .add_systems(OnEnter(GameState::PlayingNormal), spawn_score)
+        .add_systems(Update, (draw_trails, fade_trails).run_if(in_state(GameState::PlayingNormal)).run_if(in_state(Paused::Running)))
         .add_plugins(OpponentMenuPlugin);
     }
 }
@@ -26,159 +36,271 @@ impl Plugin for NormalPlugin {
 #[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
 struct Playing;
 
-fn spawn_game_scene(mut commands: Commands, window: Query<&Window, With<PrimaryWindow>>,
+/// where players and ais decide how to move their paddles each tick
+#[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadInputs;
+
+/// how far each paddle moves this tick, from -1 to 1 of its top speed
+#[derive(Resource, Default)]
+pub struct PaddleInputs(pub HashMap<Player, f32>);
+
+fn spawn_game_scene(mut commands: Commands, arena: Res<Arena>,
+    rules: Res<MatchRules>,
+    layout: Res<SelectedLayout>,
     mut events: EventWriter<GameEvent>,
 ) {
-    let window = window.single();
-    let x_pos = window.width()/2. * 0.8;
-    let paddle_size = Vec2::new(40., window.height() * 0.2);
-    commands.spawn((SpriteBundle {
-        transform: Transform::from_translation(Vec3::new(-x_pos, 0., 0.)),
-        sprite: Sprite {
-            color: Color::WHITE,
-            anchor: Anchor::Center,
-            custom_size: Some(paddle_size),
-            ..Default::default()
-        },
-        ..Default::default()
-    },
-    Name::new("Left Paddle"),
-    GameItem,
-    RigidBody::KinematicPositionBased,
-    Collider::cuboid(20., window.height() * 0.1),
-    Player::PlayerOne,
-    Paddle {size: paddle_size, speed: 250.},
-    Restitution{coefficient: 1.05, ..Default::default()}
-    ));
+    commands.insert_resource(BallIds::default());
+    match (&layout.layout, rules.mode) {
+        (Some(layout), _) => spawn_layout(&mut commands, layout, arena.size, &rules),
+        (None, NormalMode::Versus) => spawn_versus(&mut commands, arena.size),
+        (None, NormalMode::FourPlayer) => spawn_four_player(&mut commands, arena.size),
+        (None, NormalMode::Doubles) => spawn_doubles(&mut commands, arena.size),
+    }
+    events.send(GameEvent::SpawnBallRandom);
+}
+
+fn spawn_versus(commands: &mut Commands, size: Vec2) {
+    let x_pos = size.x/2. * 0.8;
+    let paddle_size = Vec2::new(40., size.y * 0.2);
+    let travel = size.y / 2. - paddle_size.y / 2.;
+    spawn_paddle(commands, Player::PlayerOne, Vec2::new(-x_pos, 0.), paddle_size, false, travel);
+    spawn_paddle(commands, Player::PlayerTwo, Vec2::new(x_pos, 0.), paddle_size, false, travel);
+    spawn_side_goals(commands, size);
+}
+
+/// walls along the top and bottom with a goal at each end
+fn spawn_side_goals(commands: &mut Commands, size: Vec2) {
+    spawn_wall(commands, "Top Wall", Vec2::new(0., size.y / 2.), Vec2::new(size.x * 0.5, WALL_THICKNESS / 2.));
+    spawn_wall(commands, "Bottom Wall", Vec2::new(0., -size.y / 2.), Vec2::new(size.x * 0.5, WALL_THICKNESS / 2.));
+
+    spawn_goal(commands, Player::PlayerOne, Vec2::new(-size.x / 2., 0.), Vec2::new(5., size.y * 0.5));
+    spawn_goal(commands, Player::PlayerTwo, Vec2::new(size.x / 2., 0.), Vec2::new(5., size.y * 0.5));
+}
+
+/// versus with a second paddle for each team, each stays in its own lane in front of the other
+fn spawn_doubles(commands: &mut Commands, size: Vec2) {
+    let back = size.x/2. * 0.8;
+    let front = size.x/2. * 0.45;
+    let paddle_size = Vec2::new(30., size.y * 0.15);
+    let travel = size.y / 2. - paddle_size.y / 2.;
+    spawn_paddle(commands, Player::PlayerOne, Vec2::new(-back, 0.), paddle_size, false, travel);
+    spawn_paddle(commands, Player::PlayerThree, Vec2::new(-front, 0.), paddle_size, false, travel);
+    spawn_paddle(commands, Player::PlayerTwo, Vec2::new(back, 0.), paddle_size, false, travel);
+    spawn_paddle(commands, Player::PlayerFour, Vec2::new(front, 0.), paddle_size, false, travel);
+    spawn_side_goals(commands, size);
+}
+
+/// a square field as big as the arena's short side, a paddle and goal on each edge
+fn spawn_four_player(commands: &mut Commands, size: Vec2) {
+    let field = size.min_element();
+    let offset = field / 2. * 0.8;
+    let corner = field * 0.1;
+    let length = field * 0.2;
+    let travel = field / 2. - corner - length / 2.;
+    for player in Player::ALL {
+        let side = four_player_side(player);
+        let horizontal = side.y != 0.;
+        let paddle_size = if horizontal {Vec2::new(length, 40.)} else {Vec2::new(40., length)};
+        spawn_paddle(commands, player, side * offset, paddle_size, horizontal, travel);
+        let goal_size = if horizontal {Vec2::new(field * 0.5, 5.)} else {Vec2::new(5., field * 0.5)};
+        spawn_goal(commands, player, side * field / 2., goal_size);
+    }
+    // corner blocks so nobody has to cover the gap between two paddles
+    for corner_pos in [Vec2::new(-1., -1.), Vec2::new(-1., 1.), Vec2::new(1., -1.), Vec2::new(1., 1.)] {
+        spawn_wall(commands, "Corner", corner_pos * (field / 2. - corner / 2.), Vec2::splat(corner / 2.));
+    }
+}
+
+/// which way each seat's goal is from the middle in four player mode
+pub fn four_player_side(player: Player) -> Vec2 {
+    match player {
+        Player::PlayerOne => Vec2::NEG_X,
+        Player::PlayerTwo => Vec2::X,
+        Player::PlayerThree => Vec2::Y,
+        Player::PlayerFour => Vec2::NEG_Y,
+    }
+}
+
+pub fn spawn_paddle(commands: &mut Commands, player: Player, position: Vec2, size: Vec2, horizontal: bool, travel: f32) -> Entity {
+    let paddle = Paddle {size, speed: 250., horizontal, travel, velocity: 0.};
     commands.spawn((SpriteBundle {
-        transform: Transform::from_translation(Vec3::new(x_pos, 0., 0.)),
+        transform: Transform::from_translation(position.extend(0.)),
         sprite: Sprite {
             color: Color::WHITE,
             anchor: Anchor::Center,
-            custom_size: Some(paddle_size),
+            custom_size: Some(size),
             ..Default::default()
         },
         ..Default::default()
     },
-    Name::new("Right Paddle"),
+    Name::new(format!("{:?} Paddle", player)),
     GameItem,
     RigidBody::KinematicPositionBased,
-    Collider::cuboid(20., window.height() * 0.1),
-    Player::PlayerTwo,
-    Paddle{size: paddle_size, speed: 250.},
-    Restitution{coefficient: 1.05, ..Default::default()}
-    ));
-
-    commands.spawn((SpriteBundle {
-        transform: Transform::from_translation(Vec3::new(0., window.height() / 2., 0.)),
-        ..Default::default()
-    },
-    Name::new("Top Wall"),
-    GameItem,
-    RigidBody::Fixed,
-    Collider::cuboid(window.width() * 0.5, 5.),
-    Restitution{coefficient: 1.0, ..Default::default()},
-    Friction{coefficient: 0.0, ..Default::default()}
-    ));
+    Collider::cuboid(size.x / 2., size.y / 2.),
+    player,
+    PaddleEffects::new(&paddle),
+    paddle,
+    // the rally speeds the ball up, see [`BallRules`]
+    Restitution{coefficient: 1.0, ..Default::default()}
+    )).id()
+}
 
+pub fn spawn_wall(commands: &mut Commands, name: &'static str, position: Vec2, half_size: Vec2) -> Entity {
     commands.spawn((SpriteBundle {
-        transform: Transform::from_translation(Vec3::new(0., -window.height() / 2., 0.)),
+        transform: Transform::from_translation(position.extend(0.)),
         ..Default::default()
     },
-    Name::new("Bottom Wall"),
+    Name::new(name),
     GameItem,
+    Wall,
     RigidBody::Fixed,
-    Collider::cuboid(window.width() * 0.5, 5.),
+    Collider::cuboid(half_size.x, half_size.y),
     Restitution{coefficient: 1.0, ..Default::default()},
     Friction{coefficient: 0., ..Default::default()}
-    ));
-
-    commands.spawn((SpriteBundle {
-        transform: Transform::from_translation(Vec3::new(-window.width() / 2., 0., 0.)),
-        ..Default::default()
-    },
-    Name::new("Left Wall"),
-    GameItem,
-    RigidBody::Fixed,
-    Sensor,
-    Collider::cuboid(5., window.height() * 0.5),
-    Player::PlayerOne,
-    Goal,
-    ));
+    )).id()
+}
 
+pub fn spawn_goal(commands: &mut Commands, player: Player, position: Vec2, half_size: Vec2) -> Entity {
     commands.spawn((SpriteBundle {
-        transform: Transform::from_translation(Vec3::new(window.width() / 2., 0., 0.)),
+        transform: Transform::from_translation(position.extend(0.)),
         ..Default::default()
     },
-    Name::new("Right Wall"),
+    Name::new(format!("{:?} Goal", player)),
     GameItem,
     RigidBody::Fixed,
     Sensor,
-    Collider::cuboid( 5., window.height() * 0.5),
-    Player::PlayerTwo,
+    Collider::cuboid(half_size.x, half_size.y),
+    player,
     Goal,
-    ));
-
-    events.send(GameEvent::SpawnBallRandom);
+    )).id()
 }
 
-fn move_paddle(
-    mut query: Query<(&mut Transform, &Player, &Paddle)>,
-    settings: Res<PlayerKeyBinds>,
-    input: Res<Input<KeyCode>>,
-    time: Res<Time>,
-    window: Query<&Window, With<PrimaryWindow>>,
-    opponent: Res<State<Opponent>>,
-    ai: Res<AiBrain>,
+fn read_paddle_inputs(
+    query: Query<(&Transform, &Player, &Paddle)>,
+    input: PlayerInput,
+    time: Res<FixedTime>,
+    brains: Res<AiBrains>,
+    mut inputs: ResMut<PaddleInputs>,
 ) {
-    let window = window.single();
-    let map_size = Vec2::new(window.width()/2., window.height()/2.);
-    for (mut transform, player, paddle) in &mut query {
-        let mut delta = match player {
-            Player::PlayerOne => get_human_delta(settings.get(Player::PlayerOne), &input),
-            Player::PlayerTwo => match opponent.get() {
-                Opponent::Human => get_human_delta(settings.get(Player::PlayerTwo), &input),
-                Opponent::Ai => ai.get_delta(),
-            }
+    inputs.0.clear();
+    for (transform, player, paddle) in &query {
+        let max_step = paddle.speed * time.period.as_secs_f32();
+        let delta = match brains.get(*player) {
+            Some(ai) => ai.control().slide(),
+            None => human_delta(&input, *player, paddle, transform.translation.truncate(), max_step),
         };
-        delta *= paddle.speed * time.delta_seconds();
-        transform.translation.y += delta;
-        transform.translation.y = transform.translation.y.clamp(-map_size.y + (paddle.size.y / 2.), map_size.y - (paddle.size.y / 2.));
+        inputs.0.insert(*player, delta);
+    }
+}
+
+pub fn move_paddle(
+    mut query: Query<(&mut Transform, &Player, &mut Paddle, Option<&PaddleEffects>)>,
+    inputs: Res<PaddleInputs>,
+    time: Res<FixedTime>,
+) {
+    let dt = time.period.as_secs_f32();
+    for (mut transform, player, mut paddle, effects) in &mut query {
+        let max_step = paddle.speed * dt;
+        let flip = if effects.map_or(false, |effects| effects.reversed) {-1.} else {1.};
+        let delta = inputs.0.get(player).copied().unwrap_or(0.) * max_step * flip;
+        let along = if paddle.horizontal {&mut transform.translation.x} else {&mut transform.translation.y};
+        let from = *along;
+        *along = (*along + delta).clamp(-paddle.travel, paddle.travel);
+        let velocity = (*along - from) / dt;
+        if paddle.velocity != velocity {
+            paddle.velocity = velocity;
+        }
     }
 }
 
-fn get_human_delta(keys: KeyBindings, input: &Input<KeyCode>) -> f32 {
-    let mut delta = 0.0;
-    if input.pressed(keys.move_up) {delta += 1.;}
-    if input.pressed(keys.move_down) {delta -= 1.;}
-    delta
+/// up on the controls moves horizontal paddles right
+fn human_delta(input: &PlayerInput, player: Player, paddle: &Paddle, position: Vec2, max_step: f32) -> f32 {
+    match input.control(player) {
+        ControlScheme::Buttons => input.delta(player),
+        ControlScheme::Pointer => match input.pointer() {
+            Some(target) if max_step > 0. => {
+                let error = if paddle.horizontal {target.x - position.x} else {target.y - position.y};
+                (error / max_step).clamp(-1., 1.)
+            },
+            _ => 0.,
+        },
+    }
 }
 
 fn spawn_ball(
     mut commands: Commands,
-    asset_sever: Res<AssetServer>,
-    window: Query<&Window, With<PrimaryWindow>>,
+    asset_sever: Option<Res<AssetServer>>,
+    arena: Res<Arena>,
+    rules: Res<MatchRules>,
+    score: Res<Score>,
     mut events: EventReader<GameEvent>,
+    mut rng: ResMut<GameRng>,
+    ball_rules: Res<BallRules>,
+    balls: Query<(), With<Ball>>,
+    layout: Res<SelectedLayout>,
+    mut ids: ResMut<BallIds>,
 ) {
-    let window = window.single();
-    let ball_size = window.height() * 0.05;
+    let rng = rng.rng();
+    let ball_size = arena.size.y * 0.05;
+    let speed = ball_rules.serve_speed;
+    let mut in_play = balls.iter().count();
     for event in events.iter() {
-        match event {
-            GameEvent::SpawnBallRandom => spawn_ball_in(&mut commands, Vec2::new(if rand::thread_rng().gen_bool(0.5) {200.} else {-200.}, rand::thread_rng().gen_range(-25.0..25.0)), ball_size, &asset_sever),
-            GameEvent::SpawnBallPlayer(player) => spawn_ball_in(&mut commands, Vec2::new(if Player::PlayerOne.eq(player) {200.} else {-200.}, rand::thread_rng().gen_range(-25.0..25.0)), ball_size, &asset_sever),
+        let color = BALL_COLOURS[in_play % BALL_COLOURS.len()];
+        let asset_sever = asset_sever.as_deref();
+        if let GameEvent::ExtraBall = event {
+            if !rules.multiball.enabled || in_play >= rules.multiball.max_balls {continue;}
+            // off from the middle at a random angle towards a random side
+            let side = match rules.mode {
+                NormalMode::FourPlayer => four_player_side(Player::ALL[rng.gen_range(0..4)]),
+                _ => if rng.gen_bool(0.5) {Vec2::X} else {Vec2::NEG_X},
+            };
+            let angle = rng.gen_range(-0.6..0.6f32);
+            let start = (side * angle.cos() + side.perp() * angle.sin()) * speed;
+            let ball = spawn_ball_in(&mut commands, ids.next(), start, ball_size, color, asset_sever);
+            commands.entity(ball).insert(Trail);
+            if let Some(serve) = serve_point(&layout, arena.size, rng) {
+                commands.entity(ball).insert(Transform::from_translation(serve.extend(0.)));
+            }
+            in_play += 1;
+            continue;
+        }
+        let ball = if rules.mode == NormalMode::FourPlayer {
+            // serve at someone still in, whoever let the last one in or not
+            let alive: Vec<Player> = Player::ALL.into_iter().filter(|player| score.get(*player) > 0).collect();
+            let Some(target) = alive.get(rng.gen_range(0..alive.len().max(1))) else {continue;};
+            let side = four_player_side(*target);
+            let start = side * speed + side.perp() * rng.gen_range(-25.0..25.0);
+            spawn_ball_in(&mut commands, ids.next(), start, ball_size, color, asset_sever)
+        } else {
+            match event {
+                GameEvent::SpawnBallPlayer(player) => spawn_ball_in(&mut commands, ids.next(), Vec2::new(if Player::PlayerOne.eq(player) {speed} else {-speed}, rng.gen_range(-25.0..25.0)), ball_size, color, asset_sever),
+                _ => spawn_ball_in(&mut commands, ids.next(), Vec2::new(if rng.gen_bool(0.5) {speed} else {-speed}, rng.gen_range(-25.0..25.0)), ball_size, color, asset_sever),
+            }
         };
+        if rules.multiball.enabled {
+            commands.entity(ball).insert(Trail);
+        }
+        if let Some(serve) = serve_point(&layout, arena.size, rng) {
+            commands.entity(ball).insert(Transform::from_translation(serve.extend(0.)));
+        }
+        in_play += 1;
     }
 }
 
-fn spawn_ball_in(commands: &mut Commands, start: Vec2, ball_size: f32, asset_sever: &AssetServer) {
+/// one per ball in play so they are easy to tell apart in multiball
+pub const BALL_COLOURS: [Color; 4] = [Color::WHITE, Color::ORANGE, Color::CYAN, Color::LIME_GREEN];
+
+pub fn spawn_ball_in(commands: &mut Commands, id: BallId, start: Vec2, ball_size: f32, color: Color, asset_sever: Option<&AssetServer>) -> Entity {
     commands.spawn((
         GameItem,
+        id,
         SpriteBundle{
             sprite: Sprite {
+            color,
             custom_size: Some(Vec2::splat(ball_size)),
             ..Default::default()
         },
-        texture: asset_sever.load("bevy.png"),
+        texture: asset_sever.map(|server| server.load("bevy.png")).unwrap_or_default(),
         ..Default::default()
     },
     Ball,
@@ -190,41 +312,219 @@ fn spawn_ball_in(commands: &mut Commands, start: Vec2, ball_size: f32, asset_sev
     LockedAxes::ROTATION_LOCKED,
     GravityScale(0.),
     Friction{coefficient: 0., ..Default::default()},
-    ));
+    ActiveEvents::COLLISION_EVENTS,
+    Ccd::enabled(),
+    )).id()
 }
 
 #[derive(Event)]
 enum GameEvent {
     SpawnBallRandom,
     SpawnBallPlayer(Player),
+    /// another ball on top of the ones in play, see [`Multiball`](super::rules::Multiball)
+    ExtraBall,
+}
+
+/// leaves a fading line of copies behind the ball
+#[derive(Component)]
+pub struct Trail;
+
+#[derive(Component)]
+struct TrailDot;
+
+/// seconds a trail dot stays on screen
+const TRAIL_LIFE: f32 = 0.25;
+
+fn draw_trails(
+    mut commands: Commands,
+    balls: Query<(&Transform, &Sprite), With<Trail>>,
+) {
+    for (transform, sprite) in &balls {
+        commands.spawn((SpriteBundle {
+            transform: Transform::from_translation(transform.translation - Vec3::Z),
+            sprite: Sprite {
+                color: sprite.color.with_a(0.5),
+                custom_size: sprite.custom_size.map(|size| size * 0.6),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        GameItem,
+        TrailDot,
+        Lifetime(TRAIL_LIFE),
+        ));
+    }
+}
+
+fn fade_trails(
+    mut dots: Query<(&mut Sprite, &Lifetime), With<TrailDot>>,
+) {
+    for (mut sprite, life) in &mut dots {
+        sprite.color.set_a((life.0 / TRAIL_LIFE).max(0.) * 0.5);
+    }
 }
 
 fn score_point(
     mut score: ResMut<Score>,
+    mut rally: ResMut<Rally>,
+    rules: Res<MatchRules>,
     query: Query<(Entity, &Player), With<Goal>>,
+    paddles: Query<(Entity, &Player), With<Paddle>>,
     balls: Query<Entity, With<Ball>>,
     physics_world: Res<RapierContext>,
     mut commands: Commands,
     mut events: EventWriter<GameEvent>,
 ) {
+    let mut in_play = balls.iter().count();
     for ball in &balls {
         for (goal, player) in &query {
             if let Some(true) = physics_world.intersection_pair(ball, goal) {
-                match player {
-                    Player::PlayerOne => score.1 += 1,
-                    Player::PlayerTwo => score.0 += 1,
+                match (rules.mode, player) {
+                    (NormalMode::FourPlayer, _) => {
+                        let lives = score.get_mut(*player);
+                        *lives = lives.saturating_sub(1);
+                        if *lives == 0 {
+                            eliminate(&mut commands, *player, goal, &paddles);
+                        }
+                    },
+                    (_, Player::PlayerOne) => score.1 = score.1.saturating_add(1),
+                    (_, _) => score.0 = score.0.saturating_add(1),
                 }
                 println!("Score = {:?}", score);
                 commands.entity(ball).despawn();
-                events.send(GameEvent::SpawnBallPlayer(*player));
+                in_play -= 1;
+                // in multiball the rally goes on until the last ball is in
+                if in_play == 0 {
+                    rally.hits = 0;
+                    events.send(GameEvent::SpawnBallPlayer(*player));
+                }
+                break;
             }
         }
     }
 }
 
+/// takes away the paddle and closes the goal off so play carries on without them
+fn eliminate(commands: &mut Commands, player: Player, goal: Entity, paddles: &Query<(Entity, &Player), With<Paddle>>) {
+    info!("{:?} is out", player);
+    for (paddle, owner) in paddles {
+        if *owner == player {
+            commands.entity(paddle).despawn_recursive();
+        }
+    }
+    commands.entity(goal)
+        .remove::<(Goal, Sensor)>()
+        .insert((Restitution{coefficient: 1.0, ..Default::default()}, Friction{coefficient: 0., ..Default::default()}));
+}
+
+fn count_rally(
+    mut events: EventReader<CollisionEvent>,
+    paddles: Query<(), With<Paddle>>,
+    balls: Query<(), With<Ball>>,
+    mut rally: ResMut<Rally>,
+    rules: Res<MatchRules>,
+    mut spawn: EventWriter<GameEvent>,
+) {
+    let every = rules.multiball.every_hits;
+    for event in events.iter() {
+        let CollisionEvent::Started(a, b, _) = event else {continue;};
+        if (paddles.contains(*a) && balls.contains(*b)) || (paddles.contains(*b) && balls.contains(*a)) {
+            rally.hits += 1;
+            rally.total_hits += 1;
+            if rules.multiball.enabled && every > 0 && rally.hits % every == 0 {
+                spawn.send(GameEvent::ExtraBall);
+            }
+        }
+    }
+}
+
+/// worked out from the match clock alone so rollback and replays agree on when balls are added
+fn multiball_timer(
+    rules: Res<MatchRules>,
+    clock: Res<MatchClock>,
+    time: Res<FixedTime>,
+    mut spawn: EventWriter<GameEvent>,
+) {
+    let every = rules.multiball.every_secs;
+    if !rules.multiball.enabled || every <= 0. {return;}
+    let before = clock.elapsed - time.period.as_secs_f32();
+    if (clock.elapsed / every).floor() > (before / every).floor() {
+        spawn.send(GameEvent::ExtraBall);
+    }
+}
+
+/// sends the ball off at an angle set by where it hit the paddle and how fast the paddle was moving
+fn apply_english(
+    mut events: EventReader<CollisionEvent>,
+    paddles: Query<(&Transform, &Paddle)>,
+    mut balls: Query<(&Transform, &mut Velocity), With<Ball>>,
+    rules: Res<MatchRules>,
+) {
+    for event in events.iter() {
+        let CollisionEvent::Started(a, b, _) = event else {continue;};
+        let (paddle, ball) = if paddles.contains(*a) {(*a, *b)} else {(*b, *a)};
+        let (Ok((paddle_pos, paddle)), Ok((ball_pos, mut velocity))) = (paddles.get(paddle), balls.get_mut(ball)) else {continue;};
+        let (along, across) = if paddle.horizontal {(Vec2::X, Vec2::Y)} else {(Vec2::Y, Vec2::X)};
+        let relative = (ball_pos.translation - paddle_pos.translation).truncate();
+        let half_length = paddle.size.dot(along) / 2.;
+        if half_length <= 0. {continue;}
+        let normal = across * relative.dot(across).signum();
+        velocity.linvel = rules.english.deflect(relative.dot(along) / half_length, normal, along, velocity.linvel.length(), paddle.velocity);
+    }
+}
+
+pub fn limit_ball_speed(
+    mut balls: Query<&mut Velocity, With<Ball>>,
+    ball_rules: Res<BallRules>,
+    rally: Res<Rally>,
+    scale: Res<BallSpeedScale>,
+) {
+    for mut velocity in &mut balls {
+        let limited = ball_rules.limit(velocity.linvel, rally.hits) * scale.0;
+        if limited != velocity.linvel {
+            velocity.linvel = limited;
+        }
+    }
+}
+
 fn spawn_score(
     mut commands: Commands,
+    rules: Res<MatchRules>,
 ) {
+    if rules.time_limit.is_some() {
+        commands.add(eml! {
+            <div c:clock with:GameItem>
+                <label bind:value=from!(MatchClock:remaining|fmt.c("{c}"))/>
+            </div>
+        });
+    }
+    spawn_scoreboard(&mut commands, rules.mode.scorers().len());
+}
+
+/// the score of each seat across the top, lives in four player
+pub fn spawn_scoreboard(commands: &mut Commands, seats: usize) {
+    if seats > 2 {
+        commands.add(eml! {
+            <div c:scoreboard with:GameItem>
+                <div c:score>
+                    <label bind:value=from!(Score:0|fmt.c("{c}"))/>
+                </div>
+                <div c:break/>
+                <div c:score>
+                    <label bind:value=from!(Score:1|fmt.c("{c}"))/>
+                </div>
+                <div c:break/>
+                <div c:score>
+                    <label bind:value=from!(Score:2|fmt.c("{c}"))/>
+                </div>
+                <div c:break/>
+                <div c:score>
+                    <label bind:value=from!(Score:3|fmt.c("{c}"))/>
+                </div>
+            </div>
+        });
+        return;
+    }
     commands.add(eml! {
         <div c:scoreboard with:GameItem>
             <div c:score>
@@ -235,7 +535,7 @@ fn spawn_score(
                 <label bind:value=from!(Score:1|fmt.c("{c}"))/>
             </div>
         </div>
-    })
+    });
 }
 
 
@@ -245,50 +545,316 @@ impl Plugin for OpponentMenuPlugin {
     fn build(&self, app: &mut App) {
         app
         .add_systems(OnEnter(GameState::OpponentSelect), spawn_opponent_menu)
-        .add_systems(OnExit(GameState::OpponentSelect), crate::menu::close_menu);
+        .add_systems(OnExit(GameState::OpponentSelect), crate::menu::close_menu)
+        .add_systems(Update, idle_attract_mode.run_if(in_state(GameState::MainMenu)))
+        .add_systems(Update, leave_attract_mode.run_if(resource_exists::<AttractMode>()))
+        .add_systems(OnEnter(GameState::GameOver), loop_attract_mode.run_if(resource_exists::<AttractMode>()))
+        .add_systems(OnEnter(GameState::MainMenu), |mut commands: Commands| commands.remove_resource::<AttractMode>());
+    }
+}
+
+fn play_normal(world: &mut World) {
+    let tuning = world.resource::<AiSettings>().tuning();
+    let seats = world.resource::<SeatSelection>();
+    let (brains, names) = (seats.brains(tuning), seat_names(seats));
+    world.insert_resource(brains);
+    world.insert_resource(names);
+    world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+}
+
+fn seat_names(seats: &SeatSelection) -> PlayerNames {
+    let humans = PlayerNames::default();
+    PlayerNames(Player::ALL.map(|player| match seats.get(player) {
+        SeatKind::Human => humans.get(player).to_string(),
+        ai => format!("{:?} Ai", ai),
+    }))
+}
+
+/// ai against ai running behind the main menu until someone presses something
+#[derive(Resource)]
+pub struct AttractMode;
+
+/// seconds on the main menu with no input before the attract mode starts
+const ATTRACT_DELAY: f32 = 30.;
+
+pub fn start_attract_mode(world: &mut World) {
+    let mut rng = rand::thread_rng();
+    let mut random_ai = || SeatKind::AI[rng.gen_range(0..SeatKind::AI.len())];
+    let seats = SeatSelection {
+        player1: random_ai(),
+        player2: random_ai(),
+        player3: random_ai(),
+        player4: random_ai(),
+    };
+    let tuning = world.resource::<AiSettings>().tuning();
+    world.insert_resource(seats.brains(tuning));
+    world.insert_resource(seat_names(&seats));
+    world.insert_resource(AttractMode);
+    world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+}
+
+fn idle_attract_mode(
+    mut commands: Commands,
+    mut idle: Local<f32>,
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+) {
+    if keys.get_just_pressed().next().is_some() || mouse.get_just_pressed().next().is_some() {
+        *idle = 0.;
+        return;
+    }
+    *idle += time.delta_seconds();
+    if *idle > ATTRACT_DELAY {
+        *idle = 0.;
+        commands.add(start_attract_mode);
     }
 }
 
+fn leave_attract_mode(
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut next: ResMut<NextState<GameState>>,
+) {
+    if keys.get_just_pressed().next().is_some() || mouse.get_just_pressed().next().is_some() {
+        next.set(GameState::MainMenu);
+    }
+}
+
+fn loop_attract_mode(
+    mut next: ResMut<NextState<GameState>>,
+) {
+    next.set(GameState::PlayingNormal);
+}
+
+/// which power-ups are on, kept next to [`MatchRules`] so each opponent menu button can show its own
+#[derive(Resource)]
+struct PowerUpToggles {
+    grow: bool,
+    shrink: bool,
+    fast_paddle: bool,
+    slow_ball: bool,
+    shield: bool,
+    reverse_controls: bool,
+    split_ball: bool,
+}
+
+impl PowerUpToggles {
+    fn new(power_ups: &PowerUps) -> Self {
+        let on = |kind| power_ups.kinds.contains(&kind);
+        PowerUpToggles {
+            grow: on(PowerUpKind::Grow),
+            shrink: on(PowerUpKind::Shrink),
+            fast_paddle: on(PowerUpKind::FastPaddle),
+            slow_ball: on(PowerUpKind::SlowBall),
+            shield: on(PowerUpKind::Shield),
+            reverse_controls: on(PowerUpKind::ReverseControls),
+            split_ball: on(PowerUpKind::SplitBall),
+        }
+    }
+}
+
+fn toggle_power_up(world: &mut World, kind: PowerUpKind) {
+    let mut rules = world.resource_mut::<MatchRules>();
+    rules.power_ups.toggle(kind);
+    let toggles = PowerUpToggles::new(&rules.power_ups);
+    world.insert_resource(toggles);
+}
+
 fn spawn_opponent_menu(
     mut commands: Commands,
+    rules: Res<MatchRules>,
 ) {
+    commands.insert_resource(PowerUpToggles::new(&rules.power_ups));
     commands.add(eml!{
         <div c:menu>
+        <div c:even>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut seats = world.resource_mut::<SeatSelection>();
+                    seats.player1 = seats.player1.next();
+                })
+            })><label bind:value=from!(SeatSelection:player1|fmt.c("Left: {c:?}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut seats = world.resource_mut::<SeatSelection>();
+                    seats.player2 = seats.player2.next();
+                })
+            })><label bind:value=from!(SeatSelection:player2|fmt.c("Right: {c:?}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut seats = world.resource_mut::<SeatSelection>();
+                    seats.player3 = seats.player3.next();
+                })
+            })><label bind:value=from!(SeatSelection:player3|fmt.c("Top / Left Front: {c:?}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut seats = world.resource_mut::<SeatSelection>();
+                    seats.player4 = seats.player4.next();
+                })
+            })><label bind:value=from!(SeatSelection:player4|fmt.c("Bottom / Right Front: {c:?}"))/></button>
+        </div>
+        <div c:even>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<MatchRules>();
+                    rules.mode = rules.mode.next();
+                })
+            })><label bind:value=from!(MatchRules:mode|fmt.c("Mode: {c:?}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<MatchRules>();
+                    rules.lives = match rules.lives {
+                        3 => 5,
+                        5 => 10,
+                        _ => 3,
+                    };
+                })
+            })><label bind:value=from!(MatchRules:lives|fmt.c("Lives: {c}"))/></button>
+        </div>
         <button on:press=run!(|c| {
             c.commands().add(|world: &mut World| {
-                world.resource_mut::<NextState<Opponent>>().set(Opponent::Human);
-                world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
-            })
-        })>
-        <label c:content value="Player2"/>
-        </button>
-        <button on:press=run!(|c| {
-            c.commands().add(|world: &mut World| {
-                world.insert_resource(AiBrain::new_simple());
-                world.resource_mut::<NextState<Opponent>>().set(Opponent::Ai);
-                world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
-            })
-        })>
-        <label c:content value="Simple Ai"/>
-        </button>
-        <button on:press=run!(|c| {
-            c.commands().add(|world: &mut World| {
-                world.insert_resource(AiBrain::new_goaly());
-                world.resource_mut::<NextState<Opponent>>().set(Opponent::Ai);
-                world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+                world.resource_mut::<AiSettings>().next();
             })
-        })>
-        <label c:content value="Goaly Ai"/>
-        </button>
+        })><label bind:value=from!(AiSettings:difficulty|fmt.c("Difficulty: {c:?}"))/></button>
+        <div c:even>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| world.resource_mut::<AiSettings>().adjust(|custom| custom.reaction_frames = custom.reaction_frames.saturating_sub(1)))
+            })><label value="-"/></button>
+            <label bind:value=from!(AiSettings:custom.reaction_frames|fmt.c("Reaction: {c} frames"))/>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| world.resource_mut::<AiSettings>().adjust(|custom| custom.reaction_frames += 1))
+            })><label value="+"/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| world.resource_mut::<AiSettings>().adjust(|custom| custom.aim_error -= 5.))
+            })><label value="-"/></button>
+            <label bind:value=from!(AiSettings:custom.aim_error|fmt.c("Aim Error: {c}"))/>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| world.resource_mut::<AiSettings>().adjust(|custom| custom.aim_error += 5.))
+            })><label value="+"/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| world.resource_mut::<AiSettings>().adjust(|custom| custom.max_speed -= 0.05))
+            })><label value="-"/></button>
+            <label bind:value=from!(AiSettings:custom.max_speed|fmt.c("Top Speed: {c}"))/>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| world.resource_mut::<AiSettings>().adjust(|custom| custom.max_speed += 0.05))
+            })><label value="+"/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| world.resource_mut::<AiSettings>().adjust(|custom| custom.hesitation -= 0.05))
+            })><label value="-"/></button>
+            <label bind:value=from!(AiSettings:custom.hesitation|fmt.c("Hesitation: {c}"))/>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| world.resource_mut::<AiSettings>().adjust(|custom| custom.hesitation += 0.05))
+            })><label value="+"/></button>
+        </div>
+        <div c:even>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<MatchRules>();
+                    rules.points_to_win = match rules.points_to_win {
+                        5 => 11,
+                        11 => 21,
+                        _ => 5,
+                    };
+                })
+            })><label bind:value=from!(MatchRules:points_to_win|fmt.c("First To: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<MatchRules>();
+                    rules.win_by_two = !rules.win_by_two;
+                })
+            })><label bind:value=from!(MatchRules:win_by_two|fmt.c("Win By Two: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<MatchRules>();
+                    rules.time_limit = match rules.time_limit {
+                        None => Some(120.),
+                        Some(limit) if limit < 300. => Some(300.),
+                        Some(_) => None,
+                    };
+                })
+            })><label bind:value=from!(MatchRules:time_limit|fmt.c("Time Limit: {c:?}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<MatchRules>();
+                    rules.multiball.enabled = !rules.multiball.enabled;
+                })
+            })><label bind:value=from!(MatchRules:multiball.enabled|fmt.c("Multiball: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<MatchRules>();
+                    rules.power_ups.enabled = !rules.power_ups.enabled;
+                })
+            })><label bind:value=from!(MatchRules:power_ups.enabled|fmt.c("Power-Ups: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(super::layout::next_layout)
+            })><label bind:value=from!(SelectedLayout:name|fmt.c("Arena: {c}"))/></button>
+        </div>
+        <div c:even>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| toggle_power_up(world, PowerUpKind::Grow))
+            })><label bind:value=from!(PowerUpToggles:grow|fmt.c("Grow: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| toggle_power_up(world, PowerUpKind::Shrink))
+            })><label bind:value=from!(PowerUpToggles:shrink|fmt.c("Shrink: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| toggle_power_up(world, PowerUpKind::FastPaddle))
+            })><label bind:value=from!(PowerUpToggles:fast_paddle|fmt.c("Fast Paddle: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| toggle_power_up(world, PowerUpKind::SlowBall))
+            })><label bind:value=from!(PowerUpToggles:slow_ball|fmt.c("Slow Ball: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| toggle_power_up(world, PowerUpKind::Shield))
+            })><label bind:value=from!(PowerUpToggles:shield|fmt.c("Shield: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| toggle_power_up(world, PowerUpKind::ReverseControls))
+            })><label bind:value=from!(PowerUpToggles:reverse_controls|fmt.c("Reverse Controls: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| toggle_power_up(world, PowerUpKind::SplitBall))
+            })><label bind:value=from!(PowerUpToggles:split_ball|fmt.c("Split Ball: {c}"))/></button>
+        </div>
         <button on:press=run!(|c| {
-            c.commands().add(|world: &mut World| {
-                world.insert_resource(AiBrain::new_smart());
-                world.resource_mut::<NextState<Opponent>>().set(Opponent::Ai);
-                world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
-            })
+            c.commands().add(play_normal)
         })>
-        <label c:content value="Smart Ai"/>
+        <label c:content value="Play"/>
         </button>
     </div>
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// how fast a paddle starting at `start` with `travel` either way says it moved after one tick of `input`
+    fn paddle_velocity(input: f32, reversed: bool, start: f32, travel: f32) -> f32 {
+        let mut world = World::new();
+        world.insert_resource(FixedTime::new_from_secs(TICK));
+        world.insert_resource(PaddleInputs(HashMap::from([(Player::PlayerOne, input)])));
+        let paddle = Paddle {size: Vec2::new(40., 100.), speed: 300., horizontal: false, travel, velocity: 0.};
+        let mut effects = PaddleEffects::new(&paddle);
+        effects.reversed = reversed;
+        let entity = world.spawn((Transform::from_xyz(0., start, 0.), Player::PlayerOne, paddle, effects)).id();
+        let mut schedule = Schedule::new();
+        schedule.add_systems(move_paddle);
+        schedule.run(&mut world);
+        world.get::<Paddle>(entity).expect("paddle is still there").velocity
+    }
+
+    #[test]
+    fn free_paddle_moves_at_full_speed() {
+        assert!((paddle_velocity(1., false, 0., 200.) - 300.).abs() < 0.01);
+    }
+
+    #[test]
+    fn reversed_paddle_moves_the_other_way() {
+        assert!((paddle_velocity(1., true, 0., 200.) + 300.).abs() < 0.01);
+    }
+
+    #[test]
+    fn pinned_paddle_is_not_moving() {
+        assert_eq!(paddle_velocity(1., false, 200., 200.), 0.);
+        // a tick from the end only gets part of the way
+        assert!((paddle_velocity(1., false, 198., 200.) - 2. / TICK).abs() < 0.01);
+    }
+}
@@ -1,19 +1,33 @@
+use std::f32::consts::{PI, TAU};
+
 use bevy::prelude::*;
 use belly::prelude::*;
 use bevy_kira_audio::{Audio, AudioControl, AudioSource};
-use crate::{GameState, PlayerKeyBinds};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::{GameCamera, GameState, Paused, Player, bindings::{PlayerInput, ControlScheme}, ai::{AiBrain, AiBrains, AiSettings, AiUpdate, PongAi}};
 use super::*;
+use super::highscore::HighScores;
+use super::rules::BallRules;
 pub struct OrbitPlugin;
 
 impl Plugin for OrbitPlugin {
     fn build(&self, app: &mut App) {
         app
         .add_event::<GameEvent>()
-        .configure_set(Update, Playing.run_if(in_state(GameState::PlayingOrbit)))
-        .add_systems(OnEnter(GameState::PlayingOrbit), spawn_orbit_world)
+        .init_resource::<OrbitRules>()
+        .init_resource::<LastTouch>()
+        .init_resource::<OrbitRun>()
+        .add_systems(OnEnter(GameState::OrbitSelect), spawn_orbit_menu)
+        .add_systems(OnExit(GameState::OrbitSelect), crate::menu::close_menu)
+        .configure_set(Update, Playing.run_if(in_state(GameState::PlayingOrbit)).run_if(in_state(Paused::Running)))
+        .configure_set(FixedUpdate, Playing.run_if(in_state(GameState::PlayingOrbit)).run_if(in_state(Paused::Running)))
+        .add_systems(OnEnter(GameState::PlayingOrbit), spawn_orbit_world.after(seed_match))
         .add_systems(OnExit(GameState::PlayingOrbit), clean_up_game)
-        .add_systems(Update, move_paddle.in_set(Playing))
-        .add_systems(Update, (process_collision_event, process_events).in_set(Playing))
+        .add_systems(FixedUpdate, move_paddle.in_set(Playing).after(AiUpdate).before(PhysicsSet::SyncBackend))
+        .add_systems(FixedUpdate, (process_collision_event, process_events, respawn_earth, limit_earth_speed).chain().in_set(Playing).after(PhysicsSet::Writeback))
+        .add_systems(OnEnter(GameState::OrbitGameOver), spawn_game_over_menu)
+        .add_systems(OnExit(GameState::OrbitGameOver), crate::menu::close_menu)
         .add_systems(OnEnter(GameState::PlayingOrbit), (particle, setup_screen_shake))
         .init_resource::<Sounds>()
         .add_systems(Update, screen_shake.in_set(Playing));
@@ -23,41 +37,142 @@ impl Plugin for OrbitPlugin {
 #[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
 struct Playing;
 
+/// how orbit is set up, picked on the orbit menu
+#[derive(Debug, Resource, Clone, Default)]
+pub struct OrbitRules {
+    /// two moons trying to knock the earth out past each other
+    pub versus: bool,
+    /// player two's moon orbits inside player one's instead of on the same path
+    pub split_radii: bool,
+    pub controls: OrbitControls,
+    /// the orbit ai flies player one's moon
+    pub autopilot: bool,
+    /// the orbit ai flies player two's moon in versus
+    pub opponent_ai: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OrbitControls {
+    /// always going anticlockwise, up speeds the moon up and down slows it down
+    #[default]
+    Classic,
+    /// up pushes anticlockwise and down clockwise, the moon keeps drifting once let go
+    Directional,
+}
+
+impl OrbitControls {
+    pub fn next(self) -> OrbitControls {
+        match self {
+            OrbitControls::Classic => OrbitControls::Directional,
+            OrbitControls::Directional => OrbitControls::Classic,
+        }
+    }
+}
+
+impl OrbitRules {
+    fn players(&self) -> &'static [Player] {
+        if self.versus {&Player::ALL[..2]} else {&Player::ALL[..1]}
+    }
+
+    fn brains(&self, world: &World) -> AiBrains {
+        let tuning = world.resource::<AiSettings>().tuning();
+        let mut brains = AiBrains::default();
+        for (player, ai) in [(Player::PlayerOne, self.autopilot), (Player::PlayerTwo, self.opponent_ai && self.versus)] {
+            if ai {
+                brains.0.insert(player, AiBrain::new_orbit().with_difficulty(tuning));
+            }
+        }
+        brains
+    }
+
+    fn radius(&self, player: Player) -> f32 {
+        match player {
+            Player::PlayerOne => PADDLE_OFFSET,
+            _ if self.split_radii => INNER_OFFSET,
+            _ => PADDLE_OFFSET,
+        }
+    }
+}
+
+/// the moon's angle around the earth's start point, how far out it orbits
+/// and how fast it is going round in radians a second
 #[derive(Component)]
-struct OrbitPaddle(f32);
+struct OrbitPaddle {
+    angle: f32,
+    radius: f32,
+    spin: f32,
+}
 
 const PADDLE_OFFSET: f32 = 250.;
+const INNER_OFFSET: f32 = 150.;
+/// top speed of a moon in radians a second
+const ORBIT_SPEED: f32 = 3.;
+/// how quickly directional controls get a moon up to speed, and how quickly it drifts to a stop
+const ORBIT_ACCELERATION: f32 = 8.;
+const ORBIT_DRAG: f32 = 2.;
+
+/// the moon that last hit the earth, in versus the other one scores if it escapes
+#[derive(Resource, Default)]
+struct LastTouch(Option<Player>);
+
+/// escapes a solo run can take before it's over
+const LIVES: u8 = 3;
+/// seconds the earth waits in the middle after an escape
+const RESPAWN_SECS: f32 = 3.;
+
+/// what's left of a solo run, the earth is held still while `respawn` counts down
+#[derive(Resource, Default)]
+struct OrbitRun {
+    lives: u8,
+    respawn: f32,
+    countdown: String,
+}
 
 #[derive(Component)]
 struct InPlay(Entity);
 
-const BALLSPEED: f32 = 100.;
 const PADDLESIZE: Vec2 = Vec2::new(25., 100.);
 const BALLSIZE: f32 = 50.;
 
 fn spawn_orbit_world(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    mut rng: ResMut<GameRng>,
+    rules: Res<OrbitRules>,
+    mut score: ResMut<Score>,
+    mut last_touch: ResMut<LastTouch>,
+    mut run: ResMut<OrbitRun>,
+    mut rally: ResMut<Rally>,
 ) {
-
+    let rng = rng.rng();
+    *score = Score::default();
+    *rally = Rally::default();
+    last_touch.0 = None;
+    let ball_rules = BallRules::orbit();
+    *run = OrbitRun { lives: LIVES, ..Default::default() };
     let id = commands.spawn(
         (GameItem, SpatialBundle::default(),
         Collider::ball(PADDLE_OFFSET + BALLSIZE),
         Sensor
     )).id();
 
-    commands.spawn((GameItem, SpriteBundle {
-        sprite: Sprite { color: Color::WHITE, custom_size: Some(PADDLESIZE), ..Default::default()},
-        transform: Transform::from_translation(Vec3::new(PADDLE_OFFSET, 0., 0.)),
-        texture: asset_server.load("moon.png"),
-        ..Default::default()
-    },
-    OrbitPaddle(0.),
-    Paddle{size: PADDLESIZE, speed: 0.05},
-    Collider::cuboid(PADDLESIZE.x / 2., PADDLESIZE.y / 2.),
-    Restitution{coefficient: 1.05, ..Default::default()},
-    Friction{coefficient: 0., ..Default::default()},
-    ));
+    for (i, player) in rules.players().iter().enumerate() {
+        let (angle, radius) = (i as f32 * PI, rules.radius(*player));
+        commands.spawn((GameItem, SpriteBundle {
+            sprite: Sprite { color: Color::WHITE, custom_size: Some(PADDLESIZE), ..Default::default()},
+            transform: Transform::from_translation(Vec3::new(angle.cos(), angle.sin(), 0.) * radius)
+                .with_rotation(Quat::from_rotation_z(angle)),
+            texture: asset_server.load("moon.png"),
+            ..Default::default()
+        },
+        OrbitPaddle { angle, radius, spin: 0. },
+        Paddle{size: PADDLESIZE, speed: ORBIT_SPEED, ..Default::default()},
+        *player,
+        Collider::cuboid(PADDLESIZE.x / 2., PADDLESIZE.y / 2.),
+        Restitution{coefficient: 1.0, ..Default::default()},
+        Friction{coefficient: 0., ..Default::default()},
+        ));
+    }
 
     commands.spawn((GameItem, SpriteBundle {
         sprite: Sprite { color: Color::WHITE, custom_size: Some(Vec2::splat(BALLSIZE)), ..Default::default()},
@@ -68,8 +183,9 @@ fn spawn_orbit_world(
     Ball,
     RigidBody::Dynamic,
     Collider::ball(BALLSIZE / 2.),
-    Velocity::linear(Vec2::new(rand::random(), rand::random()).normalize() * BALLSPEED),
+    Velocity::linear(Vec2::new(rng.gen(), rng.gen()).normalize() * ball_rules.serve_speed),
     Restitution{coefficient: 1.0, ..Default::default()},
+    Ccd::enabled(),
     Damping{linear_damping: 0., ..Default::default()},
     LockedAxes::ROTATION_LOCKED,
     GravityScale(0.),
@@ -77,57 +193,123 @@ fn spawn_orbit_world(
     InPlay(id),
     ActiveEvents::COLLISION_EVENTS,
     ));
+    commands.insert_resource(ball_rules);
 
+    if rules.versus {
+        super::normal::spawn_scoreboard(&mut commands, 2);
+        return;
+    }
     commands.add(eml! {
-        <div c:center>
+        <div c:center with:GameItem>
         <label c:orbit c:score bind:value=from!(Score:0| fmt.c("{c}"))/>
         </div>
-    })
+    });
+    commands.add(eml! {
+        <div c:lives with:GameItem>
+        <label bind:value=from!(OrbitRun:lives|fmt.c("Lives: {c}"))/>
+        <label bind:value=from!(OrbitRun:countdown|fmt.c("{c}"))/>
+        </div>
+    });
+    commands.add(eml! {
+        <div c:best with:GameItem>
+        <label bind:value=from!(HighScores:best|fmt.c("Best: {c}"))/>
+        </div>
+    });
 }
 
 fn move_paddle(
-    mut paddle: Query<(&mut Transform, &mut OrbitPaddle, &Paddle)>,
-    input: Res<Input<KeyCode>>,
-    key_binding: Res<PlayerKeyBinds>,
+    mut paddle: Query<(&mut Transform, &mut OrbitPaddle, &Paddle, &Player)>,
+    input: PlayerInput,
+    rules: Res<OrbitRules>,
+    brains: Res<AiBrains>,
+    time: Res<FixedTime>,
 ) {
-    let direction = if input.pressed(key_binding.player1.move_up) {
-        1.
-    } else if input.pressed(key_binding.player1.move_down) {
-        0.01
-    } else {
-        0.1
-    };
-
-    for (mut pos,mut orbit, paddle) in &mut paddle {
-        orbit.0 += direction * paddle.speed;
-        let x = orbit.0.cos();
-        let y = orbit.0.sin();
-        pos.translation = Vec3::new(x * PADDLE_OFFSET, y * PADDLE_OFFSET, 0.);
-        pos.rotate_z(direction * paddle.speed);
+    let dt = time.period.as_secs_f32();
+    for (mut pos, mut orbit, paddle, player) in &mut paddle {
+        let ai = brains.get(*player);
+        let delta = ai.map_or_else(|| input.delta(*player), |ai| ai.control().turn());
+        let pointer = match input.control(*player) {
+            _ if ai.is_some() => None,
+            ControlScheme::Buttons => None,
+            ControlScheme::Pointer => input.pointer(),
+        };
+        // the ai always gets directional controls so it can turn back
+        let controls = if ai.is_some() {OrbitControls::Directional} else {rules.controls};
+        orbit.spin = match (pointer, controls) {
+            // take the short way round to the pointer's angle, no faster than full speed
+            (Some(target), _) => {
+                let diff = (target.y.atan2(target.x) - orbit.angle + PI).rem_euclid(TAU) - PI;
+                (diff / dt).clamp(-paddle.speed, paddle.speed)
+            },
+            // full up is 1.0, full down is 0.01 and idle is 0.1, sticks scale in between
+            (None, OrbitControls::Classic) => paddle.speed * if delta >= 0. {
+                0.1 + 0.9 * delta
+            } else {
+                0.1 + 0.09 * delta
+            },
+            (None, OrbitControls::Directional) => {
+                let target = delta.clamp(-1., 1.) * paddle.speed;
+                let rate = if delta == 0. {ORBIT_DRAG} else {ORBIT_ACCELERATION};
+                orbit.spin + (target - orbit.spin).clamp(-rate * dt, rate * dt)
+            },
+        };
+        orbit.angle = (orbit.angle + orbit.spin * dt).rem_euclid(TAU);
+        pos.translation = Vec3::new(orbit.angle.cos(), orbit.angle.sin(), 0.) * orbit.radius;
+        // worked out from the angle rather than turned step by step so it can't drift off facing the middle
+        pos.rotation = Quat::from_rotation_z(orbit.angle);
     }
 }
 
 fn process_collision_event(
+    mut commands: Commands,
     query: Query<&InPlay>,
-    paddles: Query<(), With<Paddle>>,
+    paddles: Query<&Player, With<Paddle>>,
     mut events: EventWriter<GameEvent>,
     mut events2: EventReader<CollisionEvent>,
     mut score: ResMut<Score>,
+    mut last_touch: ResMut<LastTouch>,
+    mut run: ResMut<OrbitRun>,
+    mut rally: ResMut<Rally>,
+    mut next: ResMut<NextState<GameState>>,
+    rules: Res<OrbitRules>,
 ) {
     let ball = query.single();
     for event in events2.iter() {
         match event {
-            CollisionEvent::Started(other, _, _) => {
-                if paddles.contains(*other) {
-                    events.send(GameEvent::SpawnParticles(*other));
+            CollisionEvent::Started(a, b, _) => {
+                let other = if paddles.contains(*a) {*a} else {*b};
+                let Ok(player) = paddles.get(other) else {continue;};
+                events.send(GameEvent::SpawnParticles(other));
+                last_touch.0 = Some(*player);
+                rally.hits += 1;
+                rally.total_hits += 1;
+                if !rules.versus {
                     score.0 += 1;
                 }
             },
-            CollisionEvent::Stopped(other, _, _) => {
-                if *other == ball.0 {
-                    score.0 = 0;
-                    events.send(GameEvent::ResetBall);
+            CollisionEvent::Stopped(a, b, _) => {
+                if *a != ball.0 && *b != ball.0 {continue;}
+                rally.hits = 0;
+                if !rules.versus {
+                    run.lives = run.lives.saturating_sub(1);
+                    run.respawn = RESPAWN_SECS;
+                    if run.lives == 0 {
+                        let score = score.0;
+                        // autopilot runs don't go on the leaderboard
+                        if !rules.autopilot {
+                            commands.add(move |world: &mut World| super::highscore::finish_run(world, score));
+                        }
+                        next.set(GameState::OrbitGameOver);
+                    }
+                } else if let Some(toucher) = last_touch.0.take() {
+                    // whoever didn't knock it out gets the point
+                    for player in rules.players() {
+                        if *player != toucher {
+                            *score.get_mut(*player) += 1;
+                        }
+                    }
                 }
+                events.send(GameEvent::ResetBall);
             },
         }
     }
@@ -139,13 +321,14 @@ fn process_events(
     mut balls: Query<(&mut Transform, &mut Velocity), With<Ball>>,
     mut events: EventReader<GameEvent>,
     paddles: Query<&Transform, Without<Ball>>,
+    ball_rules: Res<BallRules>,
     sounds: Res<Sounds>,
     audio: Res<Audio>,
     mut query: Query<&mut ScreenShake>,
 ) {
     for event in events.iter() {
         match event {
-            GameEvent::ResetBall => for mut ball in &mut balls {ball.0.translation = Vec3::ZERO; ball.1.linvel = ball.1.linvel.normalize() * BALLSPEED;},
+            GameEvent::ResetBall => for mut ball in &mut balls {ball.0.translation = Vec3::ZERO; ball.1.linvel = ball.1.linvel.normalize_or_zero() * ball_rules.serve_speed;},
             GameEvent::SpawnParticles(entity) => {
                 let ball = balls.single().0;
                 let Ok(paddle) = paddles.get(*entity) else {error!("Transform on {:?} not found", entity); continue;};
@@ -190,6 +373,70 @@ fn process_events(
     }
 }
 
+/// holds the earth in the middle until the countdown runs out then sends it off in a random direction
+fn respawn_earth(
+    mut run: ResMut<OrbitRun>,
+    mut balls: Query<&mut Velocity, With<Ball>>,
+    time: Res<FixedTime>,
+    mut rng: ResMut<GameRng>,
+    ball_rules: Res<BallRules>,
+) {
+    if run.respawn <= 0. {return;}
+    run.respawn -= time.period.as_secs_f32();
+    for mut velocity in &mut balls {
+        velocity.linvel = if run.respawn > 0. {
+            Vec2::ZERO
+        } else {
+            Vec2::from_angle(rng.rng().gen_range(0. ..TAU)) * ball_rules.serve_speed
+        };
+    }
+    run.countdown = if run.respawn > 0. {format!("Next earth in {}", run.respawn.ceil())} else {String::new()};
+}
+
+/// moons bounce the earth back as fast as it came, it picks up speed from the hits in [`Rally`] up to the orbit cap instead
+fn limit_earth_speed(
+    mut balls: Query<&mut Velocity, With<Ball>>,
+    ball_rules: Res<BallRules>,
+    rally: Res<Rally>,
+) {
+    for mut velocity in &mut balls {
+        velocity.linvel = ball_rules.limit(velocity.linvel, rally.hits);
+    }
+}
+
+fn spawn_game_over_menu(
+    mut commands: Commands,
+) {
+    commands.add(eml! {
+        <div c:menu>
+            <label c:content value="Game Over"/>
+            <label c:content bind:value=from!(Score:0|fmt.c("Score: {c}"))/>
+            <label c:content bind:value=from!(HighScores:best|fmt.c("Best: {c}"))/>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::PlayingOrbit);
+                })
+            })>
+            <label c:content value="Retry"/>
+            </button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::Leaderboard);
+                })
+            })>
+            <label c:content value="Leaderboard"/>
+            </button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::MainMenu);
+                })
+            })>
+            <label c:content value="Main Menu"/>
+            </button>
+        </div>
+    });
+}
+
 #[derive(Event)]
 enum GameEvent {
     ResetBall,
@@ -198,6 +445,63 @@ enum GameEvent {
 
 use bevy_hanabi::prelude::*;
 
+fn spawn_orbit_menu(
+    mut commands: Commands,
+) {
+    commands.add(eml!{
+        <div c:menu>
+        <div c:even>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<OrbitRules>();
+                    rules.versus = !rules.versus;
+                })
+            })><label bind:value=from!(OrbitRules:versus|fmt.c("Versus: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<OrbitRules>();
+                    rules.split_radii = !rules.split_radii;
+                })
+            })><label bind:value=from!(OrbitRules:split_radii|fmt.c("Split Orbits: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<OrbitRules>();
+                    rules.controls = rules.controls.next();
+                })
+            })><label bind:value=from!(OrbitRules:controls|fmt.c("Controls: {c:?}"))/></button>
+        </div>
+        <div c:even>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<OrbitRules>();
+                    rules.autopilot = !rules.autopilot;
+                })
+            })><label bind:value=from!(OrbitRules:autopilot|fmt.c("Autopilot: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let mut rules = world.resource_mut::<OrbitRules>();
+                    rules.opponent_ai = !rules.opponent_ai;
+                })
+            })><label bind:value=from!(OrbitRules:opponent_ai|fmt.c("Player Two Ai: {c}"))/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<AiSettings>().next();
+                })
+            })><label bind:value=from!(AiSettings:difficulty|fmt.c("Difficulty: {c:?}"))/></button>
+        </div>
+        <button on:press=run!(|c| {
+            c.commands().add(|world: &mut World| {
+                let brains = world.resource::<OrbitRules>().brains(world);
+                world.insert_resource(brains);
+                world.resource_mut::<NextState<GameState>>().set(GameState::PlayingOrbit);
+            })
+        })>
+        <label c:content value="Play"/>
+        </button>
+    </div>
+    });
+}
+
 fn particle(
     mut commands: Commands,
     mut effects: ResMut<Assets<EffectAsset>>,
@@ -311,7 +615,7 @@ struct ScreenShake(Vec2);
 
 fn setup_screen_shake(
     mut commands: Commands,
-    query: Query<Entity, With<Camera>>,
+    query: Query<Entity, With<GameCamera>>,
 ) {
     for entity in &query {
         commands.entity(entity).insert((
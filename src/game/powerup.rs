@@ -0,0 +1,291 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::{GameState, Paused, Player};
+
+use super::*;
+use super::normal::{limit_ball_speed, spawn_ball_in, BALL_COLOURS};
+use super::rules::{tick_clock, MatchClock, MatchRules};
+
+/// grow and shrink multiply the paddle's length by these
+const GROW: f32 = 1.5;
+const SHRINK: f32 = 0.6;
+const FAST_PADDLE: f32 = 1.6;
+const SLOW_BALL: f32 = 0.6;
+/// seconds a pickup waits in the middle before disappearing
+const PICKUP_LIFE: f32 = 8.;
+const PICKUP_SIZE: f32 = 30.;
+
+pub struct PowerUpPlugin;
+
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<BallSpeedScale>()
+        .add_systems(FixedUpdate, (track_touches, spawn_power_ups, collect_power_ups, apply_effects).chain()
+            .after(PhysicsSet::Writeback)
+            .after(tick_clock)
+            .after(clean_up_lifetime)
+            .before(limit_ball_speed)
+            .run_if(in_state(GameState::PlayingNormal))
+            .run_if(in_state(Paused::Running)));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub enum PowerUpKind {
+    /// longer paddle for whoever collects it
+    Grow,
+    /// shorter paddles for everyone they're playing against
+    Shrink,
+    FastPaddle,
+    SlowBall,
+    /// a wall over their own goal
+    Shield,
+    /// the other side's controls are flipped
+    ReverseControls,
+    /// every ball in play splits in two
+    SplitBall,
+}
+
+impl PowerUpKind {
+    pub const ALL: [PowerUpKind; 7] = [
+        PowerUpKind::Grow,
+        PowerUpKind::Shrink,
+        PowerUpKind::FastPaddle,
+        PowerUpKind::SlowBall,
+        PowerUpKind::Shield,
+        PowerUpKind::ReverseControls,
+        PowerUpKind::SplitBall,
+    ];
+
+    fn color(self) -> Color {
+        match self {
+            PowerUpKind::Grow => Color::GREEN,
+            PowerUpKind::Shrink => Color::RED,
+            PowerUpKind::FastPaddle => Color::YELLOW,
+            PowerUpKind::SlowBall => Color::BLUE,
+            PowerUpKind::Shield => Color::SILVER,
+            PowerUpKind::ReverseControls => Color::PURPLE,
+            PowerUpKind::SplitBall => Color::ORANGE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerUps {
+    pub enabled: bool,
+    /// the kinds that can turn up
+    pub kinds: Vec<PowerUpKind>,
+    /// seconds between pickups appearing
+    pub every_secs: f32,
+    /// how long timed effects last
+    pub duration: f32,
+}
+
+impl Default for PowerUps {
+    fn default() -> Self {
+        PowerUps { enabled: false, kinds: PowerUpKind::ALL.to_vec(), every_secs: 10., duration: 8. }
+    }
+}
+
+impl PowerUps {
+    /// turns `kind` on or off, keeping `kinds` in the same order as [`PowerUpKind::ALL`]
+    pub fn toggle(&mut self, kind: PowerUpKind) {
+        let on = !self.kinds.contains(&kind);
+        self.kinds = PowerUpKind::ALL.into_iter()
+            .filter(|other| if *other == kind {on} else {self.kinds.contains(other)})
+            .collect();
+    }
+}
+
+/// a pickup waiting in the middle of the field
+#[derive(Component)]
+struct PowerUp(PowerUpKind);
+
+/// the last paddle this ball came off, they get anything it collects
+#[derive(Component)]
+struct LastTouch(Player);
+
+/// a timed effect, gone when its [`Lifetime`] runs out
+#[derive(Component)]
+struct Effect {
+    kind: PowerUpKind,
+    player: Player,
+}
+
+/// a paddle's size and speed before any effects, and whether its controls are flipped
+#[derive(Component)]
+pub struct PaddleEffects {
+    size: Vec2,
+    speed: f32,
+    travel: f32,
+    pub reversed: bool,
+}
+
+impl PaddleEffects {
+    pub fn new(paddle: &Paddle) -> PaddleEffects {
+        PaddleEffects { size: paddle.size, speed: paddle.speed, travel: paddle.travel, reversed: false }
+    }
+}
+
+/// multiplies the ball's speed on top of [`BallRules`](super::rules::BallRules)
+#[derive(Resource)]
+pub struct BallSpeedScale(pub f32);
+
+impl Default for BallSpeedScale {
+    fn default() -> Self {
+        BallSpeedScale(1.)
+    }
+}
+
+fn track_touches(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    paddles: Query<&Player, With<Paddle>>,
+    balls: Query<(), With<Ball>>,
+) {
+    for event in events.iter() {
+        let CollisionEvent::Started(a, b, _) = event else {continue;};
+        let (paddle, ball) = if paddles.contains(*a) {(*a, *b)} else {(*b, *a)};
+        let (Ok(player), true) = (paddles.get(paddle), balls.contains(ball)) else {continue;};
+        commands.entity(ball).insert(LastTouch(*player));
+    }
+}
+
+/// timed off the match clock like multiball so replays see the same pickups
+fn spawn_power_ups(
+    mut commands: Commands,
+    rules: Res<MatchRules>,
+    clock: Res<MatchClock>,
+    time: Res<FixedTime>,
+    arena: Res<Arena>,
+    mut rng: ResMut<GameRng>,
+) {
+    let power_ups = &rules.power_ups;
+    if !power_ups.enabled || power_ups.kinds.is_empty() || power_ups.every_secs <= 0. {return;}
+    let before = clock.elapsed - time.period.as_secs_f32();
+    if (clock.elapsed / power_ups.every_secs).floor() <= (before / power_ups.every_secs).floor() {return;}
+    let rng = rng.rng();
+    let kind = power_ups.kinds[rng.gen_range(0..power_ups.kinds.len())];
+    let band = arena.size.min_element() * 0.1;
+    let position = Vec2::new(rng.gen_range(-band..band), rng.gen_range(-band * 3.5..band * 3.5));
+    commands.spawn((SpriteBundle {
+        transform: Transform::from_translation(position.extend(0.)),
+        sprite: Sprite {
+            color: kind.color(),
+            custom_size: Some(Vec2::splat(PICKUP_SIZE)),
+            ..Default::default()
+        },
+        ..Default::default()
+    },
+    Name::new(format!("{:?} Power Up", kind)),
+    GameItem,
+    PowerUp(kind),
+    Lifetime(PICKUP_LIFE),
+    RigidBody::Fixed,
+    Sensor,
+    Collider::ball(PICKUP_SIZE / 2.),
+    ));
+}
+
+fn collect_power_ups(
+    mut commands: Commands,
+    rules: Res<MatchRules>,
+    pickups: Query<(Entity, &PowerUp)>,
+    balls: Query<(Entity, &Transform, &Velocity, Option<&LastTouch>), With<Ball>>,
+    goals: Query<(&Transform, &Collider, &Player), With<Goal>>,
+    arena: Res<Arena>,
+    asset_server: Option<Res<AssetServer>>,
+    physics_world: Res<RapierContext>,
+    mut ids: ResMut<BallIds>,
+) {
+    for (pickup, power_up) in &pickups {
+        // a ball nobody has hit yet goes straight through
+        let Some(player) = balls.iter()
+            .find(|(ball, ..)| physics_world.intersection_pair(*ball, pickup) == Some(true))
+            .and_then(|(.., touch)| touch.map(|touch| touch.0)) else {continue;};
+        commands.entity(pickup).despawn_recursive();
+        info!("{:?} picked up {:?}", player, power_up.0);
+        match power_up.0 {
+            PowerUpKind::Shield => {
+                let team = rules.mode.team(player);
+                let Some((goal, collider, _)) = goals.iter().find(|(.., owner)| **owner == team) else {continue;};
+                let Some(half_size) = collider.as_cuboid().map(|cuboid| cuboid.half_extents()) else {continue;};
+                let inward = -goal.translation.truncate().normalize_or_zero();
+                commands.spawn((SpriteBundle {
+                    transform: Transform::from_translation((goal.translation.truncate() + inward * 20.).extend(0.)),
+                    sprite: Sprite {
+                        color: PowerUpKind::Shield.color(),
+                        custom_size: Some(half_size * 2.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                Name::new("Shield"),
+                GameItem,
+                // the ais bounce their predictions off it like any other wall
+                Wall,
+                Lifetime(rules.power_ups.duration),
+                RigidBody::Fixed,
+                Collider::cuboid(half_size.x, half_size.y),
+                Restitution{coefficient: 1.0, ..Default::default()},
+                Friction{coefficient: 0., ..Default::default()}
+                ));
+            },
+            PowerUpKind::SplitBall => {
+                let ball_size = arena.size.y * 0.05;
+                let in_play = balls.iter().count();
+                for (i, (_, transform, velocity, _)) in balls.iter().enumerate() {
+                    let color = BALL_COLOURS[(in_play + i) % BALL_COLOURS.len()];
+                    let split = Vec2::new(velocity.linvel.x, -velocity.linvel.y);
+                    let ball = spawn_ball_in(&mut commands, ids.next(), split, ball_size, color, asset_server.as_deref());
+                    commands.entity(ball).insert((*transform, LastTouch(player)));
+                }
+            },
+            kind => {
+                commands.spawn((Effect { kind, player }, Lifetime(rules.power_ups.duration), GameItem));
+            },
+        }
+    }
+}
+
+/// works paddle sizes and speeds out from scratch every tick so an effect ending is just its entity going away
+fn apply_effects(
+    effects: Query<&Effect>,
+    mut paddles: Query<(&Player, &mut Paddle, &mut PaddleEffects, &mut Collider, &mut Sprite)>,
+    rules: Res<MatchRules>,
+    mut ball_scale: ResMut<BallSpeedScale>,
+) {
+    let rival = |a: Player, b: Player| rules.mode.team(a) != rules.mode.team(b);
+    for (player, mut paddle, mut base, mut collider, mut sprite) in &mut paddles {
+        let (mut length, mut speed, mut reversed) = (1., 1., false);
+        for effect in &effects {
+            match effect.kind {
+                PowerUpKind::Grow if effect.player == *player => length *= GROW,
+                PowerUpKind::Shrink if rival(effect.player, *player) => length *= SHRINK,
+                PowerUpKind::FastPaddle if effect.player == *player => speed *= FAST_PADDLE,
+                PowerUpKind::ReverseControls if rival(effect.player, *player) => reversed = true,
+                _ => {},
+            }
+        }
+        let along = if paddle.horizontal {Vec2::X} else {Vec2::Y};
+        let base_length = base.size.dot(along);
+        let size = base.size + along * base_length * (length - 1.);
+        if paddle.size != size {
+            paddle.size = size;
+            paddle.travel = (base.travel - (size.dot(along) - base_length) / 2.).max(0.);
+            *collider = Collider::cuboid(size.x / 2., size.y / 2.);
+            sprite.custom_size = Some(size);
+        }
+        paddle.speed = base.speed * speed;
+        if base.reversed != reversed {
+            base.reversed = reversed;
+        }
+    }
+    let slow = effects.iter().any(|effect| effect.kind == PowerUpKind::SlowBall);
+    ball_scale.0 = if slow {SLOW_BALL} else {1.};
+}
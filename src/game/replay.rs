@@ -0,0 +1,353 @@
+use std::{error::Error, path::{Path, PathBuf}, time::SystemTime};
+
+use belly::prelude::*;
+use bevy::{prelude::*, input::mouse::MouseWheel};
+use serde::{Serialize, Deserialize};
+
+use crate::{GameCamera, GameState, Paused, Player, ai::AiBrains};
+
+use super::*;
+use super::normal::{move_paddle, AttractMode, PaddleInputs, ReadInputs};
+use super::rules::MatchRules;
+
+const REPLAY_DIR: &str = "replays";
+/// how far one press of seek jumps, in ticks
+const SEEK_TICKS: usize = 5 * 60;
+/// most ticks simulated in a single frame while catching up to a seek
+const SEEK_RATE: usize = 600;
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1., 2., 4.];
+const CAMERA_SPEED: f32 = 500.;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<ReplayList>()
+        .init_resource::<ReplayStatus>()
+        .configure_set(FixedUpdate, ReadInputs.run_if(not(resource_exists::<ReplayPlayback>())))
+        .add_systems(OnEnter(GameState::PlayingNormal), start_recording.after(seed_match)
+            .run_if(not(resource_exists::<ReplayPlayback>()))
+            .run_if(not(resource_exists::<super::net::NetSession>())))
+        .add_systems(OnExit(GameState::PlayingNormal), save_replay.run_if(resource_exists::<Recording>()))
+        .add_systems(FixedUpdate, (
+            record_inputs.run_if(resource_exists::<Recording>()),
+            feed_replay.run_if(resource_exists::<ReplayPlayback>()),
+        ).after(ReadInputs).before(move_paddle).run_if(in_state(GameState::PlayingNormal)).run_if(in_state(Paused::Running)))
+        .add_systems(OnEnter(GameState::PlayingNormal), (rewind_replay, spawn_replay_hud).run_if(resource_exists::<ReplayPlayback>()))
+        .add_systems(Update, (replay_controls, seek_replay, free_camera, update_replay_status).chain()
+            .run_if(resource_exists::<ReplayPlayback>()).run_if(in_state(GameState::PlayingNormal)))
+        .add_systems(OnEnter(GameState::GameOver), stop_playback.run_if(resource_exists::<ReplayPlayback>()))
+        .add_systems(OnEnter(GameState::MainMenu), stop_playback.run_if(resource_exists::<ReplayPlayback>()))
+        .add_systems(OnEnter(GameState::Replays), (find_replays, spawn_replay_menu))
+        .add_systems(OnExit(GameState::Replays), crate::menu::close_menu);
+    }
+}
+
+/// everything needed to play a normal match again tick for tick
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub rules: MatchRules,
+    pub arena: Vec2,
+    /// paddle input of every seat on every tick, indexed by [`Player::index`]
+    pub ticks: Vec<[f32; 4]>,
+    /// `None` for the built in field
+    #[serde(default)]
+    pub layout: Option<ArenaLayout>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, Box<dyn Error>> {
+        Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, ron::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// the match being played right now
+#[derive(Resource)]
+struct Recording(Replay);
+
+fn start_recording(
+    mut commands: Commands,
+    rng: Res<GameRng>,
+    rules: Res<MatchRules>,
+    arena: Res<Arena>,
+    layout: Res<SelectedLayout>,
+    attract: Option<Res<AttractMode>>,
+) {
+    if attract.is_some() {return;}
+    commands.insert_resource(Recording(Replay {
+        seed: rng.seed(),
+        rules: rules.clone(),
+        arena: arena.size,
+        ticks: Vec::new(),
+        layout: layout.layout.clone(),
+    }));
+}
+
+fn record_inputs(
+    inputs: Res<PaddleInputs>,
+    mut recording: ResMut<Recording>,
+) {
+    let input = |player| inputs.0.get(&player).copied().unwrap_or(0.);
+    recording.0.ticks.push(Player::ALL.map(input));
+}
+
+fn save_replay(
+    mut commands: Commands,
+    recording: Res<Recording>,
+) {
+    commands.remove_resource::<Recording>();
+    if recording.0.ticks.is_empty() {return;}
+    let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or_default();
+    let path = Path::new(REPLAY_DIR).join(format!("replay-{time}.ron"));
+    match recording.0.save(&path) {
+        Ok(()) => info!("Saved replay to {}", path.display()),
+        Err(e) => error!("Failed to save replay to {}: {e}", path.display()),
+    }
+}
+
+/// a replay being watched, while this exists paddles only follow the recording
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    tick: usize,
+    /// tick to jump to, reached by simulating as fast as possible
+    seek: Option<usize>,
+    paused: bool,
+    /// index into [`SPEEDS`]
+    speed: usize,
+}
+
+fn feed_replay(
+    mut playback: ResMut<ReplayPlayback>,
+    mut inputs: ResMut<PaddleInputs>,
+) {
+    inputs.0.clear();
+    let Some(&tick) = playback.replay.ticks.get(playback.tick) else {
+        playback.paused = true;
+        return;
+    };
+    for player in Player::ALL {
+        inputs.0.insert(player, tick[player.index()]);
+    }
+    playback.tick += 1;
+}
+
+fn rewind_replay(
+    mut playback: ResMut<ReplayPlayback>,
+) {
+    playback.tick = 0;
+}
+
+fn replay_controls(
+    keys: Res<Input<KeyCode>>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut time: ResMut<Time>,
+) {
+    if keys.just_pressed(KeyCode::Space) {
+        playback.paused = !playback.paused;
+    }
+    if keys.just_pressed(KeyCode::Comma) {
+        playback.seek = Some(playback.tick.saturating_sub(SEEK_TICKS));
+    }
+    if keys.just_pressed(KeyCode::Period) {
+        playback.seek = Some((playback.tick + SEEK_TICKS).min(playback.replay.ticks.len()));
+    }
+    if keys.just_pressed(KeyCode::Minus) {
+        playback.speed = playback.speed.saturating_sub(1);
+    }
+    if keys.just_pressed(KeyCode::Equals) {
+        playback.speed = (playback.speed + 1).min(SPEEDS.len() - 1);
+    }
+    if playback.paused != time.is_paused() {
+        if playback.paused {time.pause()} else {time.unpause()}
+    }
+    if time.relative_speed() != SPEEDS[playback.speed] {
+        time.set_relative_speed(SPEEDS[playback.speed]);
+    }
+}
+
+fn seek_replay(world: &mut World) {
+    let playback = world.resource::<ReplayPlayback>();
+    let Some(target) = playback.seek else {return;};
+    if target < playback.tick {
+        // physics can't run backwards so start the match again and catch up from the first tick
+        world.insert_resource(RestartInto(GameState::PlayingNormal));
+        world.resource_mut::<NextState<GameState>>().set(GameState::Restarting);
+        return;
+    }
+    for _ in 0..SEEK_RATE {
+        if world.resource::<ReplayPlayback>().tick >= target {
+            world.resource_mut::<ReplayPlayback>().seek = None;
+            return;
+        }
+        world.run_schedule(FixedUpdate);
+    }
+}
+
+fn free_camera(
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    time: Res<Time>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<GameCamera>>,
+) {
+    let Ok((mut transform, mut projection)) = camera.get_single_mut() else {return;};
+    let mut pan = Vec2::ZERO;
+    if keys.pressed(KeyCode::W) {pan.y += 1.;}
+    if keys.pressed(KeyCode::S) {pan.y -= 1.;}
+    if keys.pressed(KeyCode::D) {pan.x += 1.;}
+    if keys.pressed(KeyCode::A) {pan.x -= 1.;}
+    // raw time so the camera still moves while the replay is paused or slowed down
+    transform.translation += (pan * CAMERA_SPEED * projection.scale * time.raw_delta_seconds()).extend(0.);
+    for event in wheel.iter() {
+        projection.scale = (projection.scale * (1. - event.y * 0.1)).clamp(0.1, 4.);
+    }
+    if keys.just_pressed(KeyCode::Home) {
+        reset_camera(&mut transform, &mut projection);
+    }
+}
+
+fn reset_camera(transform: &mut Transform, projection: &mut OrthographicProjection) {
+    transform.translation.x = 0.;
+    transform.translation.y = 0.;
+    projection.scale = 1.;
+}
+
+fn stop_playback(
+    mut commands: Commands,
+    mut time: ResMut<Time>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<GameCamera>>,
+) {
+    commands.remove_resource::<ReplayPlayback>();
+    commands.insert_resource(MatchSeed(None));
+    time.unpause();
+    time.set_relative_speed(1.);
+    for (mut transform, mut projection) in &mut camera {
+        reset_camera(&mut transform, &mut projection);
+    }
+}
+
+#[derive(Resource, Default)]
+struct ReplayStatus {
+    text: String,
+}
+
+fn update_replay_status(
+    playback: Res<ReplayPlayback>,
+    mut status: ResMut<ReplayStatus>,
+) {
+    let text = format!("{:.1}s / {:.1}s  x{}{}",
+        playback.tick as f32 * TICK,
+        playback.replay.ticks.len() as f32 * TICK,
+        SPEEDS[playback.speed],
+        if playback.paused {"  paused"} else {""},
+    );
+    if status.text != text {
+        status.text = text;
+    }
+}
+
+fn spawn_replay_hud(
+    mut commands: Commands,
+) {
+    commands.add(eml! {
+        <div c:replay with:GameItem>
+            <label bind:value=from!(ReplayStatus:text|fmt.c("{c}"))/>
+            <label c:hint value="Space pause  , . seek  - = speed  WASD wheel camera  Home reset"/>
+        </div>
+    });
+}
+
+/// saved replays, newest first
+#[derive(Resource, Default)]
+struct ReplayList {
+    files: Vec<PathBuf>,
+    selected: usize,
+    label: String,
+}
+
+impl ReplayList {
+    fn select(&mut self, selected: usize) {
+        self.selected = selected;
+        self.label = match self.files.get(selected) {
+            Some(file) => file.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+            None => "No Replays".to_string(),
+        };
+    }
+}
+
+fn find_replays(
+    mut list: ResMut<ReplayList>,
+) {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(REPLAY_DIR).into_iter().flatten().flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+        .collect();
+    files.sort();
+    files.reverse();
+    list.files = files;
+    list.select(0);
+}
+
+fn watch_replay(world: &mut World) {
+    let list = world.resource::<ReplayList>();
+    let Some(path) = list.files.get(list.selected).cloned() else {return;};
+    let replay = match Replay::load(&path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            error!("Failed to load replay {}: {e}", path.display());
+            world.resource_mut::<ReplayList>().label = "Failed To Load".to_string();
+            return;
+        }
+    };
+    world.insert_resource(MatchSeed(Some(replay.seed)));
+    world.insert_resource(replay.rules.clone());
+    world.insert_resource(Arena { size: replay.arena });
+    world.insert_resource(SelectedLayout::fixed(replay.layout.clone()));
+    world.insert_resource(AiBrains::default());
+    world.insert_resource(ReplayPlayback { replay, tick: 0, seek: None, paused: false, speed: 2 });
+    world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+}
+
+fn spawn_replay_menu(
+    mut commands: Commands,
+) {
+    commands.add(eml! {
+        <div c:menu>
+            <div c:even>
+                <button on:press=run!(|c| {
+                    c.commands().add(|world: &mut World| {
+                        let mut list = world.resource_mut::<ReplayList>();
+                        let selected = (list.selected + 1).min(list.files.len().saturating_sub(1));
+                        list.select(selected);
+                    })
+                })><label c:content value="Older"/></button>
+                <label bind:value=from!(ReplayList:label|fmt.c("{c}"))/>
+                <button on:press=run!(|c| {
+                    c.commands().add(|world: &mut World| {
+                        let mut list = world.resource_mut::<ReplayList>();
+                        let selected = list.selected.saturating_sub(1);
+                        list.select(selected);
+                    })
+                })><label c:content value="Newer"/></button>
+            </div>
+            <button on:press=run!(|c| {
+                c.commands().add(watch_replay)
+            })><label c:content value="Watch"/></button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::MainMenu);
+                })
+            })><label c:content value="Back"/></button>
+        </div>
+    });
+}
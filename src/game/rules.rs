@@ -0,0 +1,441 @@
+use belly::prelude::*;
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::{GameState, Paused, Player};
+
+use super::*;
+use super::powerup::PowerUps;
+
+pub struct RulesPlugin;
+
+impl Plugin for RulesPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<MatchRules>()
+        .register_type::<MatchRules>()
+        .init_resource::<MatchClock>()
+        .init_resource::<MatchResult>()
+        .init_resource::<BallRules>()
+        .add_systems(OnEnter(GameState::PlayingNormal), start_match)
+        .add_systems(FixedUpdate, (tick_clock, check_winner).chain().run_if(in_state(GameState::PlayingNormal)).run_if(in_state(Paused::Running)));
+
+        if app.world.contains_resource::<Headless>() {return;}
+        app
+        .add_systems(OnEnter(GameState::GameOver), spawn_game_over_menu)
+        .add_systems(OnExit(GameState::GameOver), crate::menu::close_menu);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect, Serialize, Deserialize)]
+pub enum NormalMode {
+    #[default]
+    Versus,
+    /// a goal on every side, lose a life for each one let in and the last player left wins
+    FourPlayer,
+    /// a front and back paddle on each side, player three plays with one and player four with two
+    Doubles,
+}
+
+impl NormalMode {
+    pub fn next(self) -> NormalMode {
+        match self {
+            NormalMode::Versus => NormalMode::FourPlayer,
+            NormalMode::FourPlayer => NormalMode::Doubles,
+            NormalMode::Doubles => NormalMode::Versus,
+        }
+    }
+
+    pub fn players(self) -> &'static [Player] {
+        match self {
+            NormalMode::Versus => &Player::ALL[..2],
+            NormalMode::FourPlayer | NormalMode::Doubles => &Player::ALL,
+        }
+    }
+
+    /// seats that keep a score of their own, teammates add to their captain's
+    pub fn scorers(self) -> &'static [Player] {
+        match self {
+            NormalMode::FourPlayer => &Player::ALL,
+            NormalMode::Versus | NormalMode::Doubles => &Player::ALL[..2],
+        }
+    }
+
+    pub fn ball_rules(self) -> BallRules {
+        match self {
+            NormalMode::Versus => BallRules::default(),
+            // goals on every side so a ball going straight up and down is still in play
+            NormalMode::FourPlayer => BallRules { max_speed: 700., min_horizontal: 0., ..Default::default() },
+            // twice the paddles makes for longer rallies, so speed up slower
+            NormalMode::Doubles => BallRules { speed_up: 1.03, ..Default::default() },
+        }
+    }
+
+    /// the seat whose score `player` plays for
+    pub fn team(self, player: Player) -> Player {
+        match (self, player) {
+            (NormalMode::Doubles, Player::PlayerThree) => Player::PlayerOne,
+            (NormalMode::Doubles, Player::PlayerFour) => Player::PlayerTwo,
+            _ => player,
+        }
+    }
+}
+
+#[derive(Debug, Resource, Reflect, Clone, Serialize, Deserialize)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct MatchRules {
+    pub mode: NormalMode,
+    pub points_to_win: u16,
+    pub win_by_two: bool,
+    /// in seconds, when it runs out the leader wins or the next point does on a tie
+    pub time_limit: Option<f32>,
+    /// how many goals each player can let in during four player mode
+    pub lives: u16,
+    pub english: English,
+    pub multiball: Multiball,
+    pub power_ups: PowerUps,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules { mode: NormalMode::Versus, points_to_win: 11, win_by_two: true, time_limit: None, lives: 5, english: English::default(), multiball: Multiball::default(), power_ups: PowerUps::default() }
+    }
+}
+
+/// how fast the ball goes, picked by [`NormalMode::ball_rules`] when a match starts or [`BallRules::orbit`] in orbit
+#[derive(Debug, Resource, Clone)]
+pub struct BallRules {
+    pub serve_speed: f32,
+    /// speed is multiplied by this for every paddle hit in the rally
+    pub speed_up: f32,
+    pub max_speed: f32,
+    /// share of the ball's speed that has to go across the field so it can't bounce between the walls forever
+    pub min_horizontal: f32,
+}
+
+impl Default for BallRules {
+    fn default() -> Self {
+        BallRules { serve_speed: 200., speed_up: 1.05, max_speed: 900., min_horizontal: 0.35 }
+    }
+}
+
+impl BallRules {
+    /// the earth starts slow and gains a little off every moon, with no walls it can go any direction
+    pub fn orbit() -> BallRules {
+        BallRules { serve_speed: 100., speed_up: 1.05, max_speed: 600., min_horizontal: 0. }
+    }
+
+    pub fn speed(&self, hits: u32) -> f32 {
+        (self.serve_speed * self.speed_up.powi(hits as i32)).min(self.max_speed)
+    }
+
+    /// `velocity` at the speed the rally has reached, steered off the vertical if it is too steep
+    pub fn limit(&self, velocity: Vec2, hits: u32) -> Vec2 {
+        if velocity == Vec2::ZERO {return velocity;}
+        let speed = self.speed(hits);
+        let dir = velocity.normalize();
+        let x = dir.x.abs().max(self.min_horizontal.min(1.));
+        let y = (1. - x * x).sqrt();
+        Vec2::new(x * dir.x.signum(), y * dir.y.signum()) * speed
+    }
+}
+
+/// extra balls joining in during a rally, every ball in a goal scores
+#[derive(Debug, Clone, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Multiball {
+    pub enabled: bool,
+    /// another ball each time the rally reaches a multiple of this many hits, 0 turns it off
+    pub every_hits: u32,
+    /// another ball each time this many seconds of the match go by, 0 turns it off
+    pub every_secs: f32,
+    pub max_balls: usize,
+}
+
+impl Default for Multiball {
+    fn default() -> Self {
+        Multiball { enabled: false, every_hits: 5, every_secs: 15., max_balls: 4 }
+    }
+}
+
+/// aiming off the paddle like classic pong instead of a flat bounce
+#[derive(Debug, Clone, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct English {
+    /// in degrees from straight out, reached when the ball hits the very tip of the paddle
+    pub max_angle: f32,
+    /// share of the paddle's own speed handed on to the ball, 0 turns it off
+    pub velocity_transfer: f32,
+}
+
+impl Default for English {
+    fn default() -> Self {
+        English { max_angle: 60., velocity_transfer: 0.3 }
+    }
+}
+
+impl English {
+    /// `offset` runs from -1 at one tip of the paddle to 1 at the other, `along` points towards the 1 tip,
+    /// `normal` points out of the face that was hit and `paddle_velocity` is measured along `along`
+    pub fn deflect(&self, offset: f32, normal: Vec2, along: Vec2, speed: f32, paddle_velocity: f32) -> Vec2 {
+        let max = self.max_angle.to_radians();
+        let aim = offset.clamp(-1., 1.) * max;
+        let out = (normal * aim.cos() + along * aim.sin()) * speed + along * paddle_velocity * self.velocity_transfer;
+        let angle = out.dot(along).atan2(out.dot(normal)).clamp(-max, max);
+        (normal * angle.cos() + along * angle.sin()) * speed
+    }
+}
+
+impl MatchRules {
+    /// how the match ended, or `None` while it's still going
+    pub fn result(&self, score: &Score, time_up: bool) -> Option<MatchResult> {
+        let winner = match self.mode {
+            NormalMode::FourPlayer => last_standing(score, time_up)?,
+            _ => Some(self.winner(score, time_up)?),
+        };
+        Some(MatchResult { winner, score: *score })
+    }
+
+    /// whichever side reached the points first, or is ahead when time is up
+    fn winner(&self, score: &Score, time_up: bool) -> Option<Player> {
+        let (one, two) = (score.0, score.1);
+        if time_up && one != two {
+            return Some(if one > two {Player::PlayerOne} else {Player::PlayerTwo});
+        }
+        let margin = if self.win_by_two {2} else {1};
+        if one >= self.points_to_win && one >= two + margin {
+            Some(Player::PlayerOne)
+        } else if two >= self.points_to_win && two >= one + margin {
+            Some(Player::PlayerTwo)
+        } else {
+            None
+        }
+    }
+}
+
+/// the only player with lives left, or whoever has the most when time is up,
+/// `Some(None)` is a draw when the last ones standing all went out on the same tick
+fn last_standing(score: &Score, time_up: bool) -> Option<Option<Player>> {
+    let alive: Vec<Player> = Player::ALL.into_iter().filter(|player| score.get(*player) > 0).collect();
+    match alive.as_slice() {
+        [] => return Some(None),
+        [last] => return Some(Some(*last)),
+        _ => {},
+    }
+    if !time_up {return None;}
+    let most = alive.iter().map(|player| score.get(*player)).max()?;
+    let mut leaders = alive.into_iter().filter(|player| score.get(*player) == most);
+    match (leaders.next(), leaders.next()) {
+        (Some(leader), None) => Some(Some(leader)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Resource, Default)]
+pub struct MatchClock {
+    pub elapsed: f32,
+    /// whole seconds left, only meaningful when there is a time limit
+    pub remaining: u32,
+}
+
+#[derive(Debug, Resource, Default)]
+pub struct MatchResult {
+    pub winner: Option<Player>,
+    pub score: Score,
+}
+
+fn start_match(
+    mut commands: Commands,
+    mut score: ResMut<Score>,
+    mut rally: ResMut<Rally>,
+    mut clock: ResMut<MatchClock>,
+    rules: Res<MatchRules>,
+) {
+    *score = Score::default();
+    if rules.mode == NormalMode::FourPlayer {
+        for player in Player::ALL {
+            *score.get_mut(player) = rules.lives;
+        }
+    }
+    *rally = Rally::default();
+    commands.insert_resource(rules.mode.ball_rules());
+    clock.elapsed = 0.;
+    clock.remaining = rules.time_limit.unwrap_or(0.).ceil() as u32;
+}
+
+pub fn tick_clock(
+    mut clock: ResMut<MatchClock>,
+    rules: Res<MatchRules>,
+    time: Res<FixedTime>,
+) {
+    clock.elapsed += time.period.as_secs_f32();
+    if let Some(limit) = rules.time_limit {
+        clock.remaining = (limit - clock.elapsed).max(0.).ceil() as u32;
+    }
+}
+
+fn check_winner(
+    score: Res<Score>,
+    rules: Res<MatchRules>,
+    clock: Res<MatchClock>,
+    mut result: ResMut<MatchResult>,
+    mut next: ResMut<NextState<GameState>>,
+) {
+    let time_up = rules.time_limit.map_or(false, |limit| clock.elapsed >= limit);
+    if let Some(finished) = rules.result(&score, time_up) {
+        *result = finished;
+        next.set(GameState::GameOver);
+    }
+}
+
+fn spawn_game_over_menu(
+    mut commands: Commands,
+    result: Res<MatchResult>,
+    names: Res<PlayerNames>,
+    rules: Res<MatchRules>,
+) {
+    let winner = match result.winner {
+        Some(team) if rules.mode == NormalMode::Doubles => {
+            let members = rules.mode.players().iter()
+                .filter(|player| rules.mode.team(**player) == team)
+                .map(|player| names.get(*player))
+                .collect::<Vec<_>>()
+                .join(" & ");
+            format!("{members} Win!")
+        },
+        Some(player) => format!("{} Wins!", names.get(player)),
+        None => "Draw".to_string(),
+    };
+    let score = rules.mode.scorers().iter()
+        .map(|player| result.score.get(*player).to_string())
+        .collect::<Vec<_>>()
+        .join(" - ");
+    commands.add(eml! {
+        <div c:menu>
+            <label c:content value={winner}/>
+            <label c:content value={score}/>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::PlayingNormal);
+                })
+            })>
+            <label c:content value="Rematch"/>
+            </button>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::MainMenu);
+                })
+            })>
+            <label c:content value="Main Menu"/>
+            </button>
+        </div>
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEED: f32 = 400.;
+
+    /// a ball coming off a right facing paddle whose tip at offset 1 is at the top
+    fn deflect(offset: f32, paddle_velocity: f32) -> Vec2 {
+        English::default().deflect(offset, Vec2::X, Vec2::Y, SPEED, paddle_velocity)
+    }
+
+    fn degrees(v: Vec2) -> f32 {
+        v.y.atan2(v.x).to_degrees()
+    }
+
+    #[test]
+    fn centre_hit_goes_straight_out() {
+        let out = deflect(0., 0.);
+        assert!((out - Vec2::new(SPEED, 0.)).length() < 0.01, "{out}");
+    }
+
+    #[test]
+    fn edge_hits_reach_the_max_angle() {
+        assert!((degrees(deflect(1., 0.)) - 60.).abs() < 0.01);
+        assert!((degrees(deflect(-1., 0.)) + 60.).abs() < 0.01);
+        // past the tip is treated as the tip
+        assert!((degrees(deflect(1.5, 0.)) - 60.).abs() < 0.01);
+    }
+
+    #[test]
+    fn half_way_is_half_the_angle() {
+        assert!((degrees(deflect(0.5, 0.)) - 30.).abs() < 0.01);
+    }
+
+    #[test]
+    fn moving_paddle_drags_the_ball_along() {
+        let still = degrees(deflect(0., 0.));
+        let up = degrees(deflect(0., 300.));
+        let down = degrees(deflect(0., -300.));
+        assert!(up > still && down < still, "{down} {still} {up}");
+        assert!((up + down).abs() < 0.01);
+    }
+
+    #[test]
+    fn moving_paddle_never_goes_past_the_max_angle() {
+        assert!((degrees(deflect(1., 10_000.)) - 60.).abs() < 0.01);
+    }
+
+    #[test]
+    fn speed_is_kept() {
+        for (offset, paddle_velocity) in [(0., 0.), (1., 0.), (-0.3, 250.), (0.8, -500.)] {
+            assert!((deflect(offset, paddle_velocity).length() - SPEED).abs() < 0.01);
+        }
+    }
+
+    fn first_to(points_to_win: u16, win_by_two: bool) -> MatchRules {
+        MatchRules { points_to_win, win_by_two, ..Default::default() }
+    }
+
+    #[test]
+    fn first_to_the_points_wins() {
+        let rules = first_to(11, false);
+        assert_eq!(rules.winner(&Score(11, 10, 0, 0), false), Some(Player::PlayerOne));
+        assert_eq!(rules.winner(&Score(3, 11, 0, 0), false), Some(Player::PlayerTwo));
+        assert_eq!(rules.winner(&Score(10, 10, 0, 0), false), None);
+    }
+
+    #[test]
+    fn win_by_two_plays_on_from_one_ahead() {
+        let rules = first_to(11, true);
+        assert_eq!(rules.winner(&Score(11, 10, 0, 0), false), None);
+        assert_eq!(rules.winner(&Score(12, 10, 0, 0), false), Some(Player::PlayerOne));
+        assert_eq!(rules.winner(&Score(11, 9, 0, 0), false), Some(Player::PlayerOne));
+    }
+
+    #[test]
+    fn time_up_goes_to_whoever_is_ahead() {
+        let rules = first_to(11, true);
+        assert_eq!(rules.winner(&Score(3, 5, 0, 0), true), Some(Player::PlayerTwo));
+        // level goes on to the next point
+        assert_eq!(rules.winner(&Score(4, 4, 0, 0), true), None);
+    }
+
+    #[test]
+    fn last_one_with_lives_wins_four_player() {
+        assert_eq!(last_standing(&Score(0, 2, 0, 0), false), Some(Some(Player::PlayerTwo)));
+        assert_eq!(last_standing(&Score(1, 2, 0, 0), false), None);
+        assert_eq!(last_standing(&Score(1, 3, 0, 2), true), Some(Some(Player::PlayerTwo)));
+        assert_eq!(last_standing(&Score(1, 3, 0, 3), true), None);
+    }
+
+    #[test]
+    fn going_out_together_is_a_draw() {
+        assert_eq!(last_standing(&Score(0, 0, 0, 0), false), Some(None));
+        let rules = MatchRules { mode: NormalMode::FourPlayer, ..Default::default() };
+        let result = rules.result(&Score(0, 0, 0, 0), false).expect("the match is over");
+        assert_eq!(result.winner, None);
+    }
+
+    #[test]
+    fn result_keeps_the_final_score() {
+        let result = first_to(5, false).result(&Score(5, 2, 0, 0), false).expect("the match is over");
+        assert_eq!(result.winner, Some(Player::PlayerOne));
+        assert_eq!((result.score.0, result.score.1), (5, 2));
+    }
+}
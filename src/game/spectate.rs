@@ -0,0 +1,355 @@
+use std::{collections::VecDeque, net::SocketAddr, time::{Duration, Instant}};
+
+use belly::prelude::*;
+use bevy::{prelude::*, sprite::Anchor};
+use bevy_rapier2d::prelude::*;
+
+use crate::{GameState, Player};
+
+use super::*;
+use super::net::{NetLink, NetSettings, Packet, WorldState, PORT};
+use super::normal::spawn_scoreboard;
+use super::replay::ReplayPlayback;
+
+/// spectators connect here rather than to the players' own socket
+pub const SPECTATE_PORT: u16 = PORT + 1;
+/// how far behind the newest state spectators are drawn, so there is always one to blend towards
+const INTERPOLATION_TICKS: f32 = 6.;
+/// further than this from where we should be drawing and we jump instead of catching up
+const MAX_DRIFT: f32 = 30.;
+/// a spectator or host not heard from for this long is gone
+const TIMEOUT: Duration = Duration::from_secs(5);
+const PING_INTERVAL: f32 = 1.;
+
+pub struct SpectatePlugin;
+
+impl Plugin for SpectatePlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<SpectateStatus>()
+        .add_systems(OnEnter(GameState::PlayingNormal), open_server.run_if(not(resource_exists::<ReplayPlayback>())))
+        .add_systems(Update, serve_spectators.run_if(in_state(GameState::PlayingNormal)).run_if(resource_exists::<SpectatorServer>()))
+        .add_systems(OnExit(GameState::PlayingNormal), close_server.run_if(resource_exists::<SpectatorServer>()))
+        .add_systems(Update, connect.run_if(in_state(GameState::NetLobby)).run_if(resource_exists::<Spectator>()))
+        .add_systems(OnEnter(GameState::Spectating), spawn_spectator_ui)
+        .add_systems(Update, (watch, interpolate).chain().run_if(in_state(GameState::Spectating)))
+        .add_systems(OnExit(GameState::Spectating), (clean_up_game, stop_watching))
+        .add_systems(OnEnter(GameState::MainMenu), |mut commands: Commands| commands.remove_resource::<Spectator>());
+    }
+}
+
+#[derive(Resource)]
+struct SpectatorServer {
+    link: NetLink,
+    spectators: Vec<(SocketAddr, Instant)>,
+}
+
+fn open_server(
+    mut commands: Commands,
+    settings: Res<NetSettings>,
+) {
+    if !settings.spectators {return;}
+    match NetLink::bind(SocketAddr::from(([0, 0, 0, 0], SPECTATE_PORT)), &settings) {
+        Ok(link) => {
+            info!("Spectators can watch on port {SPECTATE_PORT}");
+            commands.insert_resource(SpectatorServer { link, spectators: Vec::new() });
+        },
+        Err(e) => warn!("Can't open spectator port: {e}"),
+    }
+}
+
+fn serve_spectators(
+    mut server: ResMut<SpectatorServer>,
+    names: Res<PlayerNames>,
+    arena: Res<Arena>,
+    clock: Res<super::rules::MatchClock>,
+    score: Res<Score>,
+    paddles: Query<(&Player, &Transform, &Paddle)>,
+    balls: Query<(&BallId, &Transform, &Velocity), With<Ball>>,
+) {
+    let server = &mut *server;
+    server.link.flush();
+    for (from, packet) in server.link.receive() {
+        match packet {
+            Packet::Spectate { name } => {
+                info!("{name} is spectating from {from}");
+                server.spectators.retain(|(addr, _)| *addr != from);
+                server.spectators.push((from, Instant::now()));
+                let mut seats: Vec<Player> = paddles.iter().map(|(player, _, _)| *player).collect();
+                seats.sort_by_key(|player| player.index());
+                let names = seats.into_iter().map(|player| names.get(player).to_string()).collect();
+                server.link.send_to(from, &Packet::Watching { names, arena: arena.size });
+            },
+            Packet::Ping(sent) => {
+                if let Some((_, seen)) = server.spectators.iter_mut().find(|(addr, _)| *addr == from) {
+                    *seen = Instant::now();
+                }
+                server.link.send_to(from, &Packet::Pong(sent));
+            },
+            Packet::Bye => server.spectators.retain(|(addr, _)| *addr != from),
+            _ => {},
+        }
+    }
+    server.spectators.retain(|(_, seen)| seen.elapsed() < TIMEOUT);
+
+    let state = Packet::State(WorldState {
+        tick: (clock.elapsed / TICK).round() as u32,
+        paddles: paddles.iter().map(|(player, transform, paddle)| (*player, transform.translation.truncate(), paddle.size)).collect(),
+        balls: balls.iter().map(|(id, transform, velocity)| (*id, transform.translation.truncate(), velocity.linvel)).collect(),
+        score: *score,
+    });
+    for (spectator, _) in &server.spectators {
+        server.link.send_to(*spectator, &state);
+    }
+}
+
+fn close_server(
+    mut commands: Commands,
+    mut server: ResMut<SpectatorServer>,
+) {
+    let spectators: Vec<SocketAddr> = server.spectators.iter().map(|(addr, _)| *addr).collect();
+    for spectator in spectators {
+        server.link.send_to(spectator, &Packet::Bye);
+    }
+    commands.remove_resource::<SpectatorServer>();
+}
+
+/// watching someone else's match
+#[derive(Resource)]
+struct Spectator {
+    link: NetLink,
+    /// newest last
+    states: VecDeque<WorldState>,
+    /// the host tick currently on screen, between two of `states`
+    render_tick: f32,
+    started: Instant,
+    last_heard: Instant,
+    since_ping: f32,
+}
+
+/// what the spectator overlay shows
+#[derive(Resource, Default)]
+struct SpectateStatus {
+    names: String,
+    seats: usize,
+    latency: u32,
+}
+
+pub fn spectate(world: &mut World) {
+    let mut settings = world.resource_mut::<NetSettings>();
+    let peer = match settings.address.parse::<SocketAddr>() {
+        Ok(peer) => SocketAddr::new(peer.ip(), SPECTATE_PORT),
+        Err(_) => {
+            settings.status = format!("{} isn't an address", settings.address);
+            return;
+        }
+    };
+    match NetLink::bind(SocketAddr::from(([0, 0, 0, 0], 0)), &settings) {
+        Ok(mut link) => {
+            link.peer = Some(peer);
+            settings.status = format!("Asking {peer} to spectate");
+            world.insert_resource(Spectator {
+                link,
+                states: VecDeque::new(),
+                render_tick: 0.,
+                started: Instant::now(),
+                last_heard: Instant::now(),
+                since_ping: PING_INTERVAL,
+            });
+        },
+        Err(e) => settings.status = format!("Can't spectate: {e}"),
+    }
+}
+
+fn connect(
+    mut commands: Commands,
+    mut spectator: ResMut<Spectator>,
+    mut settings: ResMut<NetSettings>,
+    mut status: ResMut<SpectateStatus>,
+    mut next: ResMut<NextState<GameState>>,
+    time: Res<Time>,
+) {
+    spectator.link.flush();
+    spectator.since_ping += time.delta_seconds();
+    if spectator.since_ping >= PING_INTERVAL {
+        spectator.since_ping = 0.;
+        let name = settings.name.clone();
+        spectator.link.send(&Packet::Spectate { name });
+    }
+    for (_, packet) in spectator.link.receive() {
+        let Packet::Watching { names, arena } = packet else {continue;};
+        status.names = names.join(" vs ");
+        status.seats = names.len();
+        settings.status.clear();
+        spectator.last_heard = Instant::now();
+        commands.insert_resource(Arena { size: arena });
+        commands.insert_resource(Score::default());
+        next.set(GameState::Spectating);
+        return;
+    }
+}
+
+fn watch(
+    mut spectator: ResMut<Spectator>,
+    mut settings: ResMut<NetSettings>,
+    mut status: ResMut<SpectateStatus>,
+    mut next: ResMut<NextState<GameState>>,
+    time: Res<Time>,
+) {
+    spectator.link.flush();
+    spectator.since_ping += time.delta_seconds();
+    if spectator.since_ping >= PING_INTERVAL {
+        spectator.since_ping = 0.;
+        let now = spectator.started.elapsed().as_millis() as u64;
+        spectator.link.send(&Packet::Ping(now));
+    }
+    for (_, packet) in spectator.link.receive() {
+        spectator.last_heard = Instant::now();
+        match packet {
+            Packet::State(state) => {
+                let newest = spectator.states.back().map_or(0, |s| s.tick);
+                // the clock starts again on a rematch
+                if state.tick + MAX_DRIFT as u32 < newest {
+                    spectator.states.clear();
+                }
+                if spectator.states.back().map_or(true, |s| s.tick < state.tick) {
+                    spectator.states.push_back(state);
+                }
+            },
+            Packet::Pong(sent) => {
+                status.latency = (spectator.started.elapsed().as_millis() as u64).saturating_sub(sent) as u32;
+            },
+            Packet::Bye => {
+                settings.status = "The match has ended".to_string();
+                next.set(GameState::NetLobby);
+            },
+            _ => {},
+        }
+    }
+    if spectator.last_heard.elapsed() > TIMEOUT {
+        settings.status = "Lost connection to the match".to_string();
+        next.set(GameState::NetLobby);
+    }
+}
+
+/// stand-ins for the host's paddles and balls, they only move where the states say
+#[derive(Component)]
+struct Ghost;
+
+/// the [`BallId`] of the host's ball it stands in for
+#[derive(Component)]
+struct GhostBall(BallId);
+
+fn interpolate(
+    mut commands: Commands,
+    mut spectator: ResMut<Spectator>,
+    mut score: ResMut<Score>,
+    arena: Res<Arena>,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut paddles: Query<(Entity, &Player, &mut Transform, &mut Sprite), (With<Ghost>, Without<GhostBall>)>,
+    mut balls: Query<(Entity, &GhostBall, &mut Transform), Without<Ghost>>,
+) {
+    let Some(newest) = spectator.states.back() else {return;};
+    let target = newest.tick as f32 - INTERPOLATION_TICKS;
+    spectator.render_tick += time.delta_seconds() / TICK;
+    if (spectator.render_tick - target).abs() > MAX_DRIFT {
+        spectator.render_tick = target;
+    }
+    let render_tick = spectator.render_tick;
+    while spectator.states.len() > 2 && spectator.states[1].tick as f32 <= render_tick {
+        spectator.states.pop_front();
+    }
+
+    let from = &spectator.states[0];
+    let to = spectator.states.get(1).unwrap_or(from);
+    let t = if to.tick > from.tick {
+        ((render_tick - from.tick as f32) / (to.tick - from.tick) as f32).clamp(0., 1.)
+    } else {0.};
+    // past the newest state, carry the balls on along their velocity
+    let ahead = (render_tick - to.tick as f32).max(0.) * TICK;
+
+    *score = from.score;
+    for (entity, player, mut transform, mut sprite) in &mut paddles {
+        let Some((_, b, size)) = to.paddles.iter().find(|(p, _, _)| p == player) else {
+            // knocked out
+            commands.entity(entity).despawn();
+            continue;
+        };
+        let a = from.paddles.iter().find(|(p, _, _)| p == player).map_or(*b, |(_, a, _)| *a);
+        transform.translation = a.lerp(*b, t).extend(0.);
+        sprite.custom_size = Some(*size);
+    }
+    for (player, position, size) in &to.paddles {
+        if paddles.iter().any(|(_, p, _, _)| p == player) {continue;}
+        commands.spawn(ghost_paddle(*player, *position, *size));
+    }
+    let ball_size = arena.size.y * 0.05;
+    for (entity, ghost, mut transform) in &mut balls {
+        let Some((_, b, velocity)) = to.balls.iter().find(|(id, ..)| *id == ghost.0) else {
+            // scored
+            commands.entity(entity).despawn();
+            continue;
+        };
+        // a ball that wasn't in play yet starts where it was served rather than streaking over from another
+        let a = from.balls.iter().find(|(id, ..)| *id == ghost.0).map_or(*b, |(_, a, _)| *a);
+        transform.translation = (a.lerp(*b, t) + *velocity * ahead).extend(0.);
+    }
+    for (id, position, velocity) in &to.balls {
+        if balls.iter().any(|(_, ghost, _)| ghost.0 == *id) {continue;}
+        commands.spawn(ghost_ball(*id, *position + *velocity * ahead, ball_size, &asset_server));
+    }
+}
+
+fn ghost_ball(id: BallId, position: Vec2, size: f32, asset_server: &AssetServer) -> impl Bundle {
+    (SpriteBundle {
+        transform: Transform::from_translation(position.extend(0.)),
+        sprite: Sprite {
+            custom_size: Some(Vec2::splat(size)),
+            ..Default::default()
+        },
+        texture: asset_server.load("bevy.png"),
+        ..Default::default()
+    },
+    GameItem,
+    GhostBall(id),
+    )
+}
+
+fn ghost_paddle(player: Player, position: Vec2, size: Vec2) -> impl Bundle {
+    (SpriteBundle {
+        transform: Transform::from_translation(position.extend(0.)),
+        sprite: Sprite {
+            color: Color::WHITE,
+            anchor: Anchor::Center,
+            custom_size: Some(size),
+            ..Default::default()
+        },
+        ..Default::default()
+    },
+    GameItem,
+    Ghost,
+    player,
+    )
+}
+
+fn spawn_spectator_ui(
+    mut commands: Commands,
+    status: Res<SpectateStatus>,
+) {
+    spawn_scoreboard(&mut commands, status.seats);
+    commands.add(eml! {
+        <div c:spectate with:GameItem>
+            <label bind:value=from!(SpectateStatus:names|fmt.c("{c}"))/>
+            <label bind:value=from!(SpectateStatus:latency|fmt.c("Ping: {c}ms"))/>
+        </div>
+    });
+}
+
+fn stop_watching(
+    mut commands: Commands,
+    mut spectator: ResMut<Spectator>,
+) {
+    spectator.link.send(&Packet::Bye);
+    commands.remove_resource::<Spectator>();
+}
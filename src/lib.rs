@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+
+mod menu;
+
+pub mod game;
+
+pub mod ai;
+
+mod bindings;
+
+/// the whole windowed game, expects `DefaultPlugins` to already be added
+pub struct PongPlugin;
+
+impl Plugin for PongPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_plugins(bevy_kira_audio::AudioPlugin)
+        .add_plugins(belly::prelude::BellyPlugin)
+        .add_plugins(bevy_editor_pls::EditorPlugin::default())
+        .add_state::<GameState>()
+        .add_state::<Paused>()
+        .add_plugins(menu::MenuPlugins)
+        .insert_resource(bevy_pkv::PkvStore::new("PhaestusFox", "Pong"))
+        .add_systems(Startup, spawn_cam)
+        .init_resource::<bindings::PlayerKeyBinds>()
+        .add_systems(Update, back_to_main_menu)
+        .add_plugins(game::GamePlugin)
+        .add_plugins(ai::AiPlugin)
+        .add_plugins(bevy_hanabi::HanabiPlugin);
+    }
+}
+
+/// the camera looking at the game, the editor brings its own cameras so anything
+/// that wants the one on screen should filter on this
+#[derive(Component)]
+pub struct GameCamera;
+
+fn spawn_cam(mut commands: Commands) {
+    commands.spawn((Camera2dBundle::default(), GameCamera));
+}
+
+#[derive(Default, States, Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    SettingsMenu,
+    OpponentSelect,
+    OrbitSelect,
+    PlayingNormal,
+    PlayingOrbit,
+    GameOver,
+    OrbitGameOver,
+    Restarting,
+    Replays,
+    Leaderboard,
+    NetLobby,
+    Spectating,
+}
+
+#[derive(Default, States, Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Paused {
+    #[default]
+    Running,
+    Paused,
+    Settings,
+}
+
+fn back_to_main_menu(
+    mut next: ResMut<NextState<GameState>>,
+    mut next_pause: ResMut<NextState<Paused>>,
+    state: Res<State<GameState>>,
+    paused: Res<State<Paused>>,
+    input: Res<Input<KeyCode>>,
+    online: Option<Res<game::NetSession>>,
+) {
+    if !input.just_pressed(KeyCode::Escape) {return;}
+    match (state.get(), paused.get()) {
+        // the other side keeps playing so an online match can't be paused, only left
+        (GameState::PlayingNormal, _) if online.is_some() => next.set(GameState::MainMenu),
+        (GameState::PlayingNormal | GameState::PlayingOrbit, Paused::Running) => next_pause.set(Paused::Paused),
+        (GameState::PlayingNormal | GameState::PlayingOrbit, Paused::Paused) => next_pause.set(Paused::Running),
+        (GameState::PlayingNormal | GameState::PlayingOrbit, Paused::Settings) => next_pause.set(Paused::Paused),
+        _ => next.set(GameState::MainMenu),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Component, serde::Serialize, serde::Deserialize)]
+pub enum Player {
+    PlayerOne,
+    PlayerTwo,
+    PlayerThree,
+    PlayerFour,
+}
+
+impl Player {
+    pub const ALL: [Player; 4] = [Player::PlayerOne, Player::PlayerTwo, Player::PlayerThree, Player::PlayerFour];
+
+    pub fn index(self) -> usize {
+        match self {
+            Player::PlayerOne => 0,
+            Player::PlayerTwo => 1,
+            Player::PlayerThree => 2,
+            Player::PlayerFour => 3,
+        }
+    }
+}
+
@@ -1,6 +1,6 @@
 use bevy::{prelude::*, input::{keyboard::KeyboardInput, ButtonState}};
 use belly::prelude::*;
-use crate::{GameState, PlayerKeyBinds};
+use crate::{GameState, Paused, Player, bindings::{Action, Binding, PlayerKeyBinds}};
 
 pub struct MenuPlugins;
 
@@ -10,6 +10,7 @@ impl PluginGroup for MenuPlugins {
         .add(MenuCore)
         .add(MainMenuPlugin)
         .add(SettingsPlugin)
+        .add(PausePlugin)
     }
 }
 
@@ -40,9 +41,14 @@ impl Plugin for SettingsPlugin {
     fn build(&self, app: &mut App) {
         app
         .add_state::<SetBinding>()
-        .add_systems(Update, set_key_binding.run_if(not(in_state(SetBinding::None))))
+        // ahead of escape going back a menu so it only stops the listening
+        .add_systems(Update, set_key_binding.run_if(not(in_state(SetBinding::None))).before(crate::back_to_main_menu))
+        .init_resource::<BindingWarnings>()
+        .add_systems(Update, update_binding_warnings.run_if(resource_changed::<PlayerKeyBinds>()))
         .add_systems(OnEnter(GameState::SettingsMenu), spawn_settings_menu)
         .add_systems(OnExit(GameState::SettingsMenu), close_menu)
+        .add_systems(OnEnter(Paused::Settings), spawn_settings_menu)
+        .add_systems(OnExit(Paused::Settings), close_menu)
         .add_systems(Update, name_state::<SetBinding>.run_if(state_changed::<SetBinding>()));
     }
 }
@@ -62,12 +68,42 @@ fn spawn_main_menu(
 
             <button on:press=run!(|c| {
                 c.commands().add(|world: &mut World| {
-                    world.resource_mut::<NextState<GameState>>().set(GameState::PlayingOrbit);
+                    world.resource_mut::<NextState<GameState>>().set(GameState::OrbitSelect);
                 })
             })>
             <label c:content value="Play Orbit"/>
             </button>
 
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::NetLobby);
+                })
+            })>
+            <label c:content value="Play Online"/>
+            </button>
+
+            <button on:press=run!(|c| {
+                c.commands().add(crate::game::start_attract_mode)
+            })>
+            <label c:content value="Watch Ai vs Ai"/>
+            </button>
+
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::Replays);
+                })
+            })>
+            <label c:content value="Replays"/>
+            </button>
+
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::Leaderboard);
+                })
+            })>
+            <label c:content value="Leaderboard"/>
+            </button>
+
             <button on:press=run!(|c| {
                 c.commands().add(|world: &mut World| {
                     world.resource_mut::<NextState<GameState>>().set(GameState::SettingsMenu);
@@ -79,6 +115,65 @@ fn spawn_main_menu(
     });
 }
 
+struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(Paused::Paused), spawn_pause_menu)
+        .add_systems(OnExit(Paused::Paused), close_pause_menu);
+    }
+}
+
+fn spawn_pause_menu(
+    mut commands: Commands
+) {
+    commands.add(eml! {
+        <div c:menu c:pause>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<Paused>>().set(Paused::Running);
+                })
+            })>
+            <label c:content value="Resume"/>
+            </button>
+
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    let current = *world.resource::<State<GameState>>().get();
+                    world.insert_resource(crate::game::RestartInto(current));
+                    world.resource_mut::<NextState<GameState>>().set(GameState::Restarting);
+                })
+            })>
+            <label c:content value="Restart"/>
+            </button>
+
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<Paused>>().set(Paused::Settings);
+                })
+            })>
+            <label c:content value="Settings"/>
+            </button>
+
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    world.resource_mut::<NextState<GameState>>().set(GameState::MainMenu);
+                })
+            })>
+            <label c:content value="Quit to Menu"/>
+            </button>
+        </div>
+    });
+}
+
+// the pause menu has its own class so leaving the pause state
+// can't take a freshly spawned main menu with it
+fn close_pause_menu(
+    mut elements: Elements,
+) {
+    elements.select(".pause").remove();
+}
+
 pub fn close_menu(
     mut elements: Elements,
 ) {
@@ -89,57 +184,134 @@ pub fn close_menu(
 enum SetBinding {
     #[default]
     None,
-    P1Up,
-    P1Down,
-    P2Up,
-    P2Down,
+    Key,
+    Pad,
+}
+
+/// the slot that the next key or button press will be bound to
+#[derive(Resource)]
+struct BindingTarget(Binding);
+
+fn listen_for(world: &mut World, device: SetBinding, binding: Binding) {
+    world.insert_resource(BindingTarget(binding));
+    world.resource_mut::<NextState<SetBinding>>().set(device);
+}
+
+/// conflicting and unbound actions, shown under the bindings
+#[derive(Resource, Default)]
+struct BindingWarnings {
+    text: String,
+}
+
+/// a seat's key row and gamepad row, `$field` is the seat's field on [`PlayerKeyBinds`]
+macro_rules! player_bindings {
+    ($commands:ident, $player:ident, $field:ident, $name:tt) => {{
+        let rows = $commands.spawn_empty().id();
+        $commands.add(eml! {
+            <div {rows} c:bindings>
+                <div c:even>
+                    <label value=$name/>
+                    <button on:press=run!(|c| {
+                        c.commands().add(|world: &mut World| listen_for(world, SetBinding::Key, Binding::new(Player::$player, Action::MoveUp, 0)))
+                    })><label bind:value=from!(PlayerKeyBinds:$field.move_up|fmt.c("Up: {c}"))/></button>
+                    <button on:press=run!(|c| {
+                        c.commands().add(|world: &mut World| listen_for(world, SetBinding::Key, Binding::new(Player::$player, Action::MoveUp, 1)))
+                    })><label value="Alt"/></button>
+                    <button on:press=run!(|c| {
+                        c.commands().add(|world: &mut World| listen_for(world, SetBinding::Key, Binding::new(Player::$player, Action::MoveDown, 0)))
+                    })><label bind:value=from!(PlayerKeyBinds:$field.move_down|fmt.c("Down: {c}"))/></button>
+                    <button on:press=run!(|c| {
+                        c.commands().add(|world: &mut World| listen_for(world, SetBinding::Key, Binding::new(Player::$player, Action::MoveDown, 1)))
+                    })><label value="Alt"/></button>
+                    <button on:press=run!(|c| {
+                        c.commands().add(|world: &mut World| {
+                            let mut bindings = world.resource_mut::<PlayerKeyBinds>();
+                            let bindings = bindings.get_mut(Player::$player);
+                            bindings.control = bindings.control.next();
+                            save_key_bindings(world);
+                        })
+                    })><label bind:value=from!(PlayerKeyBinds:$field.control|fmt.c("Control: {c:?}"))/></button>
+                </div>
+                <div c:even>
+                    <label value="Pad: "/>
+                    <button on:press=run!(|c| {
+                        c.commands().add(|world: &mut World| cycle_gamepad(world, Player::$player))
+                    })><label bind:value=from!(PlayerKeyBinds:$field.gamepad.gamepad|fmt.c("Pad: {c:?}"))/></button>
+                    <button on:press=run!(|c| {
+                        c.commands().add(|world: &mut World| {
+                            let mut bindings = world.resource_mut::<PlayerKeyBinds>();
+                            let pad = &mut bindings.get_mut(Player::$player).gamepad;
+                            pad.axis = next_axis(pad.axis);
+                            save_key_bindings(world);
+                        })
+                    })><label bind:value=from!(PlayerKeyBinds:$field.gamepad.axis|fmt.c("Stick: {c:?}"))/></button>
+                    <button on:press=run!(|c| {
+                        c.commands().add(|world: &mut World| listen_for(world, SetBinding::Pad, Binding::new(Player::$player, Action::MoveUp, 0)))
+                    })><label bind:value=from!(PlayerKeyBinds:$field.gamepad.move_up|fmt.c("Up: {c:?}"))/></button>
+                    <button on:press=run!(|c| {
+                        c.commands().add(|world: &mut World| listen_for(world, SetBinding::Pad, Binding::new(Player::$player, Action::MoveDown, 0)))
+                    })><label bind:value=from!(PlayerKeyBinds:$field.gamepad.move_down|fmt.c("Down: {c:?}"))/></button>
+                </div>
+            </div>
+        });
+        rows
+    }};
 }
 
 fn spawn_settings_menu(
     mut commands: Commands
 ) {
+    // from! needs a field path so each seat is spelled out, in the same order as Player::ALL
+    let rows = [
+        player_bindings!(commands, PlayerOne, player1, "Player One: "),
+        player_bindings!(commands, PlayerTwo, player2, "Player Two: "),
+        player_bindings!(commands, PlayerThree, player3, "Player Three: "),
+        player_bindings!(commands, PlayerFour, player4, "Player Four: "),
+    ];
+    let players = commands.spawn_empty().id();
     commands.add(eml! {
         <div c:menu>
-            <div c:even>
-                <label value="Player One: "/>
-                <button on:press=run!(|c| {
-                    c.commands().add(|world: &mut World| {
-                        world.resource_mut::<NextState<SetBinding>>().set(SetBinding::P1Up);
-                    })
-                })><label bind:value=from!(PlayerKeyBinds:player1.move_up|fmt.c("Up: {c:?}"))/></button>
-                <button on:press=run!(|c| {
-                    c.commands().add(|world: &mut World| {
-                        world.resource_mut::<NextState<SetBinding>>().set(SetBinding::P1Down);
-                    })
-                })><label bind:value=from!(PlayerKeyBinds:player1.move_down|fmt.c("Down: {c:?}"))/></button>
-            </div>
-            <div c:even>
-                <label value="Player Two: "/>
-                <button on:press=run!(|c| {
-                    c.commands().add(|world: &mut World| {
-                        world.resource_mut::<NextState<SetBinding>>().set(SetBinding::P2Up);
-                    })
-                })><label bind:value=from!(PlayerKeyBinds:player2.move_up|fmt.c("Up: {c:?}"))/></button>
-                <button on:press=run!(|c| {
-                    c.commands().add(|world: &mut World| {
-                        world.resource_mut::<NextState<SetBinding>>().set(SetBinding::P2Down);
-                    })
-                })><label bind:value=from!(PlayerKeyBinds:player2.move_down|fmt.c("Down: {c:?}"))/></button>
-            </div>
+            <div {players} c:bindings/>
+            <label c:warning bind:value=from!(BindingWarnings:text)/>
+            <button on:press=run!(|c| {
+                c.commands().add(|world: &mut World| {
+                    if Paused::Settings.eq(world.resource::<State<Paused>>().get()) {
+                        world.resource_mut::<NextState<Paused>>().set(Paused::Paused);
+                    } else {
+                        world.resource_mut::<NextState<GameState>>().set(GameState::MainMenu);
+                    }
+                })
+            })>
+            <label c:content value="Back"/>
+            </button>
         </div>
     });
+    commands.entity(players).push_children(&rows);
 }
 
 
 const BAND_KEYS: [KeyCode; 2] = [KeyCode::Escape, KeyCode::Return];
+/// clears the slot being bound instead of binding to it
+const CLEAR_KEY: KeyCode = KeyCode::Back;
+/// stops listening and leaves the slot as it was, for keys and gamepads alike
+const CANCEL_KEY: KeyCode = KeyCode::Escape;
 
 fn set_key_binding(
+    mut commands: Commands,
+    mut keys: ResMut<Input<KeyCode>>,
     state: Res<State<SetBinding>>,
+    target: Res<BindingTarget>,
     mut events: EventReader<KeyboardInput>,
+    buttons: Res<Input<GamepadButton>>,
     mut next: ResMut<NextState<SetBinding>>,
-    mut pkv: ResMut<bevy_pkv::PkvStore>,
     mut bindings: ResMut<PlayerKeyBinds>,
 ) {
+    if keys.just_pressed(CANCEL_KEY) {
+        keys.clear_just_pressed(CANCEL_KEY);
+        events.clear();
+        next.set(SetBinding::None);
+        return;
+    }
     let mut new_key = None;
     for event in events.iter() {
         if event.state != ButtonState::Pressed {continue;};
@@ -148,20 +320,64 @@ fn set_key_binding(
             new_key = Some(key_bind);
         }
     }
-    if let Some(new) = new_key {
-        match state.get() {
-            SetBinding::None => unreachable!(),
-            SetBinding::P1Up => bindings.set(crate::Player::PlayerOne, true, new),
-            SetBinding::P1Down => bindings.set(crate::Player::PlayerOne, false, new),
-            SetBinding::P2Up => bindings.set(crate::Player::PlayerTwo, true, new),
-            SetBinding::P2Down => bindings.set(crate::Player::PlayerTwo, false, new),
-        }
+    let new_button = buttons.get_just_pressed().next().map(|button| button.button_type);
+    let binding = target.0;
+    let done = match (state.get(), new_key, new_button) {
+        (SetBinding::None, _, _) => unreachable!(),
+        (SetBinding::Key, Some(CLEAR_KEY), _) => {bindings.clear(binding); true},
+        (SetBinding::Key, Some(new), _) => {bindings.set(binding, new); true},
+        (SetBinding::Pad, _, Some(new)) => {bindings.set_pad_button(binding.player, binding.action, new); true},
+        _ => false,
+    };
+    if done {
         next.set(SetBinding::None);
-        if let Err(e) = pkv.set("KeyBind", &*bindings) {error!("{e}")};
+        commands.add(save_key_bindings);
         info!("Settings: {:#?}", bindings);
     }
 }
 
+fn update_binding_warnings(
+    bindings: Res<PlayerKeyBinds>,
+    mut warnings: ResMut<BindingWarnings>,
+) {
+    let mut text = String::new();
+    for (a, b) in bindings.conflicts() {
+        let key = bindings.key(a).expect("conflicts are always bound");
+        text.push_str(&format!("{:?} {:?} and {:?} {:?} are both on {:?}\n", a.player, a.action, b.player, b.action, key));
+    }
+    for (player, action) in bindings.unbound() {
+        text.push_str(&format!("{:?} {:?} is not bound\n", player, action));
+    }
+    warnings.text = text;
+}
+
+/// only called after an edit, so binds that failed to load aren't overwritten just by starting up
+fn save_key_bindings(world: &mut World) {
+    world.resource_scope(|world, mut pkv: Mut<bevy_pkv::PkvStore>| {
+        if let Err(e) = pkv.set("KeyBind", world.resource::<PlayerKeyBinds>()) {error!("{e}")};
+    });
+}
+
+/// steps a player through no gamepad and then each connected one in turn
+fn cycle_gamepad(world: &mut World, player: Player) {
+    let mut pads: Vec<usize> = world.resource::<Gamepads>().iter().map(|pad| pad.id).collect();
+    pads.sort();
+    let mut bindings = world.resource_mut::<PlayerKeyBinds>();
+    let bindings = &mut bindings.get_mut(player).gamepad;
+    bindings.gamepad = match bindings.gamepad {
+        None => pads.first().copied(),
+        Some(current) => pads.iter().copied().find(|id| *id > current),
+    };
+    save_key_bindings(world);
+}
+
+fn next_axis(axis: GamepadAxisType) -> GamepadAxisType {
+    match axis {
+        GamepadAxisType::LeftStickY => GamepadAxisType::RightStickY,
+        _ => GamepadAxisType::LeftStickY,
+    }
+}
+
 fn name_state<T: States>(state: Res<State<T>>) {
     info!("You are in state: {:?}", state.get());
 }
\ No newline at end of file